@@ -0,0 +1,73 @@
+/*
+ * A minimal C API to classify evdev devices with whodat.
+ *
+ * Link against libwhodat_capi.so, built with
+ *   cargo build -p whodat-capi
+ *
+ * Ownership rules:
+ * - the fd passed to whodat_classify_evdev() remains owned by the caller
+ * - the returned device must be released with whodat_device_free()
+ * - a device does not refer to the kernel device, it remains valid after
+ *   the kernel device was removed
+ */
+
+#ifndef WHODAT_H
+#define WHODAT_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The capability bits, these never change */
+#define WHODAT_CAP_KEYBOARD        (1u << 0)
+#define WHODAT_CAP_POINTER         (1u << 1)
+#define WHODAT_CAP_POINTINGSTICK   (1u << 2)
+#define WHODAT_CAP_TOUCHPAD        (1u << 3)
+#define WHODAT_CAP_CLICKPAD        (1u << 4)
+#define WHODAT_CAP_PRESSUREPAD     (1u << 5)
+#define WHODAT_CAP_TOUCHSCREEN     (1u << 6)
+#define WHODAT_CAP_TRACKBALL       (1u << 7)
+#define WHODAT_CAP_JOYSTICK        (1u << 8)
+#define WHODAT_CAP_GAMEPAD         (1u << 9)
+#define WHODAT_CAP_TABLET          (1u << 10)
+#define WHODAT_CAP_TABLET_SCREEN   (1u << 11)
+#define WHODAT_CAP_TABLET_EXTERNAL (1u << 12)
+#define WHODAT_CAP_TABLET_PAD      (1u << 13)
+#define WHODAT_CAP_SWITCH          (1u << 14)
+#define WHODAT_CAP_RACING_WHEEL    (1u << 15)
+#define WHODAT_CAP_STYLUS          (1u << 16)
+#define WHODAT_CAP_DIAL            (1u << 17)
+#define WHODAT_CAP_ACCELEROMETER   (1u << 18)
+#define WHODAT_CAP_NUMPAD          (1u << 19)
+
+struct whodat_device;
+
+/*
+ * Classify the evdev device the fd points to. The fd is not read from,
+ * written to or closed.
+ *
+ * Returns NULL if the device cannot be classified.
+ */
+struct whodat_device *whodat_classify_evdev(int fd);
+
+/*
+ * Write the capability bitmask of the device into out, at most len 32-bit
+ * words. The first word holds bits 0-31.
+ *
+ * Returns the number of words required for the full bitmask or -EINVAL
+ * if the device is NULL. Pass NULL and 0 to query the number of words.
+ */
+int whodat_device_capabilities(const struct whodat_device *device,
+                               uint32_t *out, size_t len);
+
+/* Release the device. Passing NULL is a noop. */
+void whodat_device_free(struct whodat_device *device);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* WHODAT_H */
@@ -0,0 +1,111 @@
+//! A minimal C API to classify evdev devices with whodat, for consumers
+//! that are not written in Rust. See `include/whodat.h` for the C
+//! declarations.
+//!
+//! Ownership rules:
+//! - the fd passed to [`whodat_classify_evdev`] remains owned by the caller,
+//!   whodat works on a duplicate of the fd and closes that duplicate before
+//!   returning
+//! - the returned handle is owned by the caller and must be released with
+//!   [`whodat_device_free`], a handle must not be used after it was released
+//! - a handle does not refer to the kernel device, it remains valid after
+//!   the device was removed
+//!
+//! From Python the library can be used with `ctypes`:
+//!
+//! ```python
+//! import ctypes, os
+//!
+//! lib = ctypes.CDLL("libwhodat_capi.so")
+//! lib.whodat_classify_evdev.restype = ctypes.c_void_p
+//! lib.whodat_device_capabilities.argtypes = [ctypes.c_void_p, ctypes.c_void_p, ctypes.c_size_t]
+//! lib.whodat_device_free.argtypes = [ctypes.c_void_p]
+//!
+//! fd = os.open("/dev/input/event0", os.O_RDONLY)
+//! device = lib.whodat_classify_evdev(fd)
+//! mask = (ctypes.c_uint32 * 1)()
+//! lib.whodat_device_capabilities(device, mask, 1)
+//! lib.whodat_device_free(device)
+//! os.close(fd)
+//! ```
+
+use std::os::fd::{BorrowedFd, RawFd};
+use std::os::raw::c_int;
+use whodat::{EvdevDevice, HasCapability};
+
+/// An opaque handle to a classified device
+pub struct WhodatDevice {
+    device: EvdevDevice,
+}
+
+/// Classify the evdev device the fd points to. The fd must be ready for
+/// `ioctl()`, no data is read or written on this fd.
+///
+/// Returns a handle to the device or `NULL` if the device cannot be
+/// classified, e.g. because the fd is not an evdev device or the device
+/// was removed.
+///
+/// # Safety
+///
+/// The fd must be an open file descriptor for the duration of this call.
+#[no_mangle]
+pub unsafe extern "C" fn whodat_classify_evdev(fd: RawFd) -> *mut WhodatDevice {
+    if fd < 0 {
+        return std::ptr::null_mut();
+    }
+    let fd = match BorrowedFd::borrow_raw(fd).try_clone_to_owned() {
+        Ok(fd) => fd,
+        Err(_) => return std::ptr::null_mut(),
+    };
+    match EvdevDevice::from_fd(fd) {
+        Ok(device) => Box::into_raw(Box::new(WhodatDevice { device })),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// Write the capabilities of the device into `out` as bitmask of
+/// `WHODAT_CAP_*` bits, see [`whodat::Capability::as_bit`]. The bitmask is
+/// an array of 32-bit words, the first word holds bits 0-31.
+///
+/// At most `len` words are written. Returns the number of words required
+/// for the full bitmask or `-EINVAL` if the device is `NULL`. A caller
+/// may pass `NULL` and zero to query the number of words.
+///
+/// # Safety
+///
+/// The device must be a handle returned by [`whodat_classify_evdev`] and
+/// `out` must point to at least `len` writable words.
+#[no_mangle]
+pub unsafe extern "C" fn whodat_device_capabilities(
+    device: *const WhodatDevice,
+    out: *mut u32,
+    len: usize,
+) -> c_int {
+    let device = match device.as_ref() {
+        Some(device) => device,
+        None => return -libc::EINVAL,
+    };
+    let mask = device
+        .device
+        .capabilities()
+        .iter()
+        .fold(0, |mask, c| mask | c.as_bit());
+    let words = [mask];
+    if !out.is_null() {
+        std::ptr::copy_nonoverlapping(words.as_ptr(), out, len.min(words.len()));
+    }
+    words.len() as c_int
+}
+
+/// Release the device. Passing `NULL` is a noop.
+///
+/// # Safety
+///
+/// The device must be a handle returned by [`whodat_classify_evdev`] that
+/// was not released yet.
+#[no_mangle]
+pub unsafe extern "C" fn whodat_device_free(device: *mut WhodatDevice) {
+    if !device.is_null() {
+        drop(Box::from_raw(device));
+    }
+}
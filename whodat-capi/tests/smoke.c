@@ -0,0 +1,59 @@
+/*
+ * Smoke test for the C API. Build and run from the workspace root with
+ *
+ *   cargo build -p whodat-capi
+ *   cc -Iwhodat-capi/include whodat-capi/tests/smoke.c \
+ *      -Ltarget/debug -lwhodat_capi -o target/smoke
+ *   LD_LIBRARY_PATH=target/debug target/smoke /dev/input/event0
+ *
+ * Exits with 77 (skipped) if the device node cannot be opened.
+ */
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <unistd.h>
+
+#include "whodat.h"
+
+int main(int argc, char **argv)
+{
+	const char *path = argc > 1 ? argv[1] : "/dev/input/event0";
+	struct whodat_device *device;
+	uint32_t mask = 0;
+	int fd, nwords;
+
+	if (whodat_device_capabilities(NULL, &mask, 1) != -EINVAL)
+		return 1;
+	whodat_device_free(NULL);
+
+	fd = open(path, O_RDONLY | O_NONBLOCK);
+	if (fd < 0) {
+		fprintf(stderr, "Unable to open %s, skipping\n", path);
+		return 77;
+	}
+
+	device = whodat_classify_evdev(fd);
+	if (!device) {
+		fprintf(stderr, "Unable to classify %s\n", path);
+		close(fd);
+		return 1;
+	}
+
+	nwords = whodat_device_capabilities(device, NULL, 0);
+	if (nwords < 1 || whodat_device_capabilities(device, &mask, 1) != nwords)
+		return 1;
+
+	printf("%s: capabilities 0x%08x%s%s\n", path, mask,
+	       mask & WHODAT_CAP_KEYBOARD ? " keyboard" : "",
+	       mask & WHODAT_CAP_POINTER ? " pointer" : "");
+
+	whodat_device_free(device);
+
+	/* The fd is still ours */
+	if (fcntl(fd, F_GETFD) < 0)
+		return 1;
+	close(fd);
+
+	return 0;
+}
@@ -1,9 +1,20 @@
-use clap::{arg, command, Parser, Subcommand};
+use clap::{arg, command, Parser, Subcommand, ValueEnum};
+use serde::Serialize;
 use std::error::Error;
 use std::fs::File;
 use std::path::PathBuf;
-use std::os::fd::OwnedFd;
-use whodat::{AttachedDevice, EvdevDevice, HasCapability, HasParent, PhysicalDevice};
+use std::os::fd::{AsRawFd, OwnedFd};
+use whodat::{
+    AttachedDevice, Builder, Capability, Capture, EvdevDevice, HasCapability, HasParent,
+    HidrawDevice, PhysicalDevice, Quirk,
+};
+
+/// The exit code on any error, e.g. an unsupported path
+const EXIT_ERROR: i32 = 1;
+/// The exit code if a filter did not match any device
+const EXIT_NO_MATCH: i32 = 3;
+/// The exit code if whodat and udev disagree on any device
+const EXIT_MISMATCH: i32 = 4;
 
 #[derive(Parser)]
 #[command(version)]
@@ -11,6 +22,10 @@ struct Cli {
     #[arg(short, long, action = clap::ArgAction::Count)]
     debug: u8,
 
+    /// The output format
+    #[arg(long, value_enum, default_value_t = Format::Text, global = true)]
+    format: Format,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -18,118 +33,747 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     // shows information about a given device.
-    Show { path: std::path::PathBuf },
-    Tree { paths: Vec<std::path::PathBuf> },
+    Show {
+        #[arg(required_unless_present = "replay")]
+        path: Option<std::path::PathBuf>,
+        /// Classify the device from a file written by the capture command instead
+        #[arg(long, conflicts_with = "path")]
+        replay: Option<std::path::PathBuf>,
+    },
+    /// Capture everything needed to classify an evdev device into a file
+    Capture {
+        path: std::path::PathBuf,
+        /// The file to write the capture to, defaults to stdout
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+    },
+    Tree {
+        /// Only show devices with this capability, may be given multiple times
+        #[arg(long, long_help = only_help())]
+        only: Vec<Capability>,
+        /// Keep running and print the tree again whenever a device is added or
+        /// removed. Without any paths, all devices on the system are shown.
+        /// Exit with Ctrl-C.
+        #[arg(long)]
+        watch: bool,
+        paths: Vec<std::path::PathBuf>,
+    },
+    /// Compare the capabilities of each evdev device with udev's ID_INPUT_*
+    /// tags. Devices that cannot be opened are reported and skipped.
+    /// Exits with status 4 if they disagree on any device.
+    Verify {
+        #[arg(required = true)]
+        paths: Vec<std::path::PathBuf>,
+    },
+    /// Classify a device from its USB vendor and product id and optionally
+    /// its name, without access to the device. Exits with status 3 if
+    /// nothing can be determined.
+    Lookup {
+        /// The vendor and product id in hex, e.g. 054c:0ce6
+        #[arg(value_parser = parse_usbid)]
+        usbid: (u16, u16),
+        /// The device name as advertised by the kernel, e.g. "Wireless Controller"
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Inspect the database of well-known devices compiled into whodat
+    Quirks {
+        #[command(subcommand)]
+        command: QuirksCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum QuirksCommands {
+    /// List all quirks
+    List,
+    /// Show the quirks for the device with the given USB vendor and product id
+    Lookup {
+        /// The vendor and product id in hex, e.g. 054c:0ce6
+        #[arg(value_parser = parse_usbid)]
+        usbid: (u16, u16),
+    },
+}
+
+/// Parse a `vid:pid` pair of hex ids
+fn parse_usbid(s: &str) -> Result<(u16, u16), String> {
+    let parse =
+        |id: &str| u16::from_str_radix(id, 16).map_err(|e| format!("Invalid id '{id}': {e}"));
+    match s.split_once(':') {
+        Some((vid, pid)) => Ok((parse(vid)?, parse(pid)?)),
+        None => Err(format!("Expected vid:pid, e.g. 054c:0ce6, got '{s}'")),
+    }
+}
+
+/// The long help for the `--only` argument, listing all capabilities
+fn only_help() -> String {
+    let caps: Vec<String> = Capability::all().iter().map(|c| c.to_string()).collect();
+    format!(
+        "Only show devices with this capability, may be given multiple times.\n\n\
+         Possible values: {}",
+        caps.join(", ")
+    )
+}
+
+/// The output format for all commands
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+}
+
+#[derive(Serialize)]
+struct EvdevInfo {
+    name: String,
+    bustype: String,
+    vid: u16,
+    pid: u16,
+    version: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    driver: Option<String>,
+    udev: Vec<String>,
+    capabilities: Vec<String>,
+}
+
+impl From<&EvdevDevice> for EvdevInfo {
+    fn from(device: &EvdevDevice) -> Self {
+        EvdevInfo {
+            name: device.name().to_string(),
+            bustype: format!("{:?}", device.bustype()),
+            vid: device.vid(),
+            pid: device.pid(),
+            version: device.version(),
+            driver: device.driver(),
+            udev: device.udev_types().clone(),
+            capabilities: device.capabilities().iter().map(|c| c.to_string()).collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct HidrawInfo {
+    name: String,
+    bustype: String,
+    vid: u16,
+    pid: u16,
+    capabilities: Vec<String>,
+}
+
+impl From<&HidrawDevice> for HidrawInfo {
+    fn from(device: &HidrawDevice) -> Self {
+        HidrawInfo {
+            name: device.name().to_string(),
+            bustype: format!("{:?}", device.bustype()),
+            vid: device.vid(),
+            pid: device.pid(),
+            capabilities: device.capabilities().iter().map(|c| c.to_string()).collect(),
+        }
+    }
 }
 
-fn print_evdev(device: &EvdevDevice, prefix: &str) {
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum ChildInfo {
+    Evdev(EvdevInfo),
+    Hidraw(HidrawInfo),
+}
+
+#[derive(Serialize)]
+struct ParentInfo {
+    name: String,
+    abstract_types: Vec<String>,
+    capabilities: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    children: Vec<ChildInfo>,
+}
+
+impl ParentInfo {
+    /// Build the parent info, with the children filled in from the tree if given.
+    /// Where `only` is not empty, only children with any of those capabilities
+    /// are included.
+    fn new(
+        parent: &PhysicalDevice,
+        tree: Option<&whodat::DeviceTree>,
+        only: &[Capability],
+    ) -> Self {
+        let children = match tree {
+            Some(tree) => tree
+                .children(parent)
+                .filter_map(|child| match child {
+                    AttachedDevice::Evdev(evdev) if has_any(evdev, only) => {
+                        Some(ChildInfo::Evdev(evdev.into()))
+                    }
+                    AttachedDevice::Hidraw(hidraw) if has_any(hidraw, only) => {
+                        Some(ChildInfo::Hidraw(hidraw.into()))
+                    }
+                    _ => None,
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+
+        ParentInfo {
+            name: parent.name().to_string(),
+            abstract_types: parent.abstract_types().iter().map(|t| format!("{t:?}")).collect(),
+            capabilities: parent.capabilities().iter().map(|c| c.to_string()).collect(),
+            children,
+        }
+    }
+}
+
+/// Returns true if the device has any of the given capabilities or
+/// the given capabilities are empty
+fn has_any(device: &dyn HasCapability, only: &[Capability]) -> bool {
+    only.is_empty() || device.capabilities().iter().any(|c| only.contains(c))
+}
+
+#[derive(Serialize)]
+struct QuirkInfo {
+    vid: u16,
+    pid: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<u16>,
+    add: Vec<String>,
+    remove: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    abstract_type: Option<String>,
+}
+
+impl From<&Quirk> for QuirkInfo {
+    fn from(quirk: &Quirk) -> Self {
+        QuirkInfo {
+            vid: quirk.vid(),
+            pid: quirk.pid(),
+            version: quirk.version(),
+            add: quirk.added().iter().map(|c| c.to_string()).collect(),
+            remove: quirk.removed().iter().map(|c| c.to_string()).collect(),
+            abstract_type: quirk.abstract_type().map(|t| format!("{t:?}")),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct VerifyInfo {
+    path: PathBuf,
+    name: String,
+    udev: Vec<String>,
+    capabilities: Vec<String>,
+    /// Capabilities whodat found that udev did not tag
+    whodat_only: Vec<String>,
+    /// Capabilities udev tagged that whodat did not find
+    udev_only: Vec<String>,
+    /// Why the device could not be compared, e.g. a permission error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl VerifyInfo {
+    /// Compare the device's capabilities with its udev tags. Capabilities
+    /// udev has no tag for, e.g. clickpads, are never a mismatch.
+    fn new(path: &std::path::Path, device: &EvdevDevice) -> Self {
+        let udev_caps = device.udev_capabilities();
+        let caps = device.capabilities();
+        let to_strings = |caps: Vec<&Capability>| caps.iter().map(|c| c.to_string()).collect();
+        VerifyInfo {
+            path: path.to_path_buf(),
+            name: device.name().to_string(),
+            udev: device.udev_types().clone(),
+            capabilities: caps.iter().map(|c| c.to_string()).collect(),
+            whodat_only: to_strings(
+                caps.iter()
+                    .filter(|c| c.udev_property().is_some() && !udev_caps.contains(c))
+                    .collect(),
+            ),
+            udev_only: to_strings(udev_caps.iter().filter(|c| !caps.contains(c)).collect()),
+            error: None,
+        }
+    }
+
+    /// A device that could not be opened. This is not a mismatch.
+    fn failed(path: &std::path::Path, error: &dyn Error) -> Self {
+        VerifyInfo {
+            path: path.to_path_buf(),
+            name: String::new(),
+            udev: vec![],
+            capabilities: vec![],
+            whodat_only: vec![],
+            udev_only: vec![],
+            error: Some(error.to_string()),
+        }
+    }
+
+    fn is_mismatch(&self) -> bool {
+        !self.whodat_only.is_empty() || !self.udev_only.is_empty()
+    }
+}
+
+#[derive(Serialize)]
+struct LookupInfo {
+    vid: u16,
+    pid: u16,
+    #[serde(flatten)]
+    device: ParentInfo,
+    confidence: f32,
+}
+
+#[derive(Serialize)]
+struct ShowInfo {
+    device: ChildInfo,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent: Option<ParentInfo>,
+}
+
+fn print_evdev(device: &EvdevInfo, prefix: &str) {
     println!("{prefix}- evdev:");
-    println!("{prefix}    name: {}", device.name());
-    println!("{prefix}    id: {:04x}:{:04x}", device.vid(), device.pid());
-    println!("{prefix}    udev: {:?}", device.udev_types());
+    println!("{prefix}    name: {}", device.name);
+    println!(
+        "{prefix}    id: {} {:04x}:{:04x} v{:04x}",
+        device.bustype, device.vid, device.pid, device.version
+    );
+    if let Some(driver) = &device.driver {
+        println!("{prefix}    driver: {driver}");
+    }
+    println!("{prefix}    udev: {:?}", device.udev);
     println!("{prefix}    capabilities:");
-    for c in device.capabilities().into_iter() {
-        println!("{prefix}    - {c:?}");
+    for c in device.capabilities.iter() {
+        println!("{prefix}    - {c}");
     }
 }
 
-fn print_parent(parent: &PhysicalDevice, prefix: &str) {
-    let atypes = parent.abstract_types();
-    let atype = atypes.first().unwrap();
-    println!("{prefix}- parent:");
-    println!("{prefix}    type: {atype:?}");
+fn print_hidraw(device: &HidrawInfo, prefix: &str) {
+    println!("{prefix}- hidraw:");
+    println!("{prefix}    name: {}", device.name);
+    println!("{prefix}    id: {} {:04x}:{:04x}", device.bustype, device.vid, device.pid);
     println!("{prefix}    capabilities:");
-    for c in parent.capabilities().into_iter() {
-        println!("{prefix}    - {c:?}");
+    for c in device.capabilities.iter() {
+        println!("{prefix}    - {c}");
     }
 }
 
-fn show_evdev(path: &std::path::PathBuf) -> Result<(), Box<dyn Error>> {
-    assert!(path.starts_with("/dev/input"));
-    let f = File::open(path)?;
+fn print_verify(info: &VerifyInfo) {
+    if let Some(error) = &info.error {
+        println!("- {}: error: {error}", info.path.display());
+        return;
+    }
+    let status = if info.is_mismatch() { "MISMATCH" } else { "ok" };
+    println!("- {}: {status}", info.path.display());
+    println!("    name: {}", info.name);
+    println!("    udev: {:?}", info.udev);
+    for c in info.whodat_only.iter() {
+        println!("    + {c} (whodat only)");
+    }
+    for c in info.udev_only.iter() {
+        println!("    - {c} (udev only)");
+    }
+}
 
-    let mut tree = whodat::DeviceTree::new();
-    let idx = tree.attach_evdev(OwnedFd::from(f))?;
-    let device = tree.get_device(&idx).unwrap();
-    match device {
-        AttachedDevice::Evdev(device) => {
-            println!("For evdev device {path:?}:");
-            print_evdev(&device, "");
+fn print_lookup(info: &LookupInfo) {
+    println!("For device {:04x}:{:04x}:", info.vid, info.pid);
+    print_parent(&info.device, "");
+    println!("    confidence: {:.2}", info.confidence);
+}
+
+fn print_quirk(quirk: &QuirkInfo) {
+    match quirk.version {
+        Some(version) => println!("- quirk: {:04x}:{:04x} v{version:04x}", quirk.vid, quirk.pid),
+        None => println!("- quirk: {:04x}:{:04x}", quirk.vid, quirk.pid),
+    }
+    if !quirk.add.is_empty() {
+        println!("    add: {}", quirk.add.join(", "));
+    }
+    if !quirk.remove.is_empty() {
+        println!("    remove: {}", quirk.remove.join(", "));
+    }
+    if let Some(atype) = &quirk.abstract_type {
+        println!("    type: {atype}");
+    }
+}
+
+fn print_child(child: &ChildInfo, prefix: &str) {
+    match child {
+        ChildInfo::Evdev(evdev) => print_evdev(evdev, prefix),
+        ChildInfo::Hidraw(hidraw) => print_hidraw(hidraw, prefix),
+    }
+}
 
-            let pidx = device.parent();
-            let parent = tree
-                .get_parent_device(&pidx)
-                .expect(format!("Bug: no parent for device {:?}", &device).as_str());
-            print_parent(&parent, "");
+fn print_parent(parent: &ParentInfo, prefix: &str) {
+    println!("{prefix}- parent:");
+    println!("{prefix}    name: {}", parent.name);
+    match parent.abstract_types.first() {
+        Some(atype) => println!("{prefix}    type: {atype}"),
+        None => println!("{prefix}    type: unknown"),
+    }
+    println!("{prefix}    capabilities:");
+    for c in parent.capabilities.iter() {
+        println!("{prefix}    - {c}");
+    }
+    if !parent.children.is_empty() {
+        println!("{prefix}    children:");
+        for child in parent.children.iter() {
+            print_child(child, &format!("{prefix}    "));
         }
-        _ => {}
     }
+}
 
+fn print_show(
+    path: &std::path::PathBuf,
+    kind: &str,
+    info: &ShowInfo,
+    format: Format,
+) -> Result<(), Box<dyn Error>> {
+    match format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(info)?),
+        Format::Text => {
+            println!("For {kind} device {path:?}:");
+            print_child(&info.device, "");
+            if let Some(parent) = &info.parent {
+                print_parent(parent, "");
+            }
+        }
+    }
     Ok(())
 }
 
-fn show_hidraw(path: &std::path::PathBuf) -> Result<(), Box<dyn Error>> {
-    assert!(path.starts_with("/dev/hidraw"));
-    let _fd = File::open(path)?;
+fn show_evdev(path: &std::path::PathBuf, format: Format) -> Result<(), Box<dyn Error>> {
+    let device = EvdevDevice::from_path(path)?;
+    show_evdev_device(path, device, format)
+}
+
+fn show_replay(path: &std::path::PathBuf, format: Format) -> Result<(), Box<dyn Error>> {
+    let capture = Capture::deserialize(&std::fs::read_to_string(path)?)?;
+    let device = EvdevDevice::from_source(&capture);
+    show_evdev_device(path, device, format)
+}
+
+fn show_evdev_device(
+    path: &std::path::PathBuf,
+    device: EvdevDevice,
+    format: Format,
+) -> Result<(), Box<dyn Error>> {
+    let mut tree = whodat::DeviceTree::new();
+    let idx = tree.attach_evdev_device(device);
+    let device = tree
+        .get_device(&idx)
+        .and_then(|d| d.as_evdev())
+        .expect("Bug: evdev device not in tree");
+    let parent = tree
+        .get_parent_device(&device.parent())
+        .unwrap_or_else(|| panic!("Bug: no parent for device {device:?}"));
+    let info = ShowInfo {
+        device: ChildInfo::Evdev(device.into()),
+        parent: Some(ParentInfo::new(parent, None, &[])),
+    };
+    print_show(path, "evdev", &info, format)
+}
+
+fn show_hidraw(path: &std::path::PathBuf, format: Format) -> Result<(), Box<dyn Error>> {
+    let f = open(path)?;
+
+    let device = HidrawDevice::from_fd(OwnedFd::from(f))?;
+    let info = ShowInfo {
+        device: ChildInfo::Hidraw((&device).into()),
+        parent: None,
+    };
+    print_show(path, "hidraw", &info, format)?;
 
     Ok(())
 }
 
-fn show(path: &std::path::PathBuf) -> Result<(), Box<dyn Error>> {
-    let cpath = std::fs::canonicalize(path)?;
-    let devnode = cpath.as_os_str().to_str().unwrap();
-    if devnode.starts_with("/dev/input/") {
-        show_evdev(path)?
+fn show(path: &std::path::PathBuf, format: Format) -> Result<(), Box<dyn Error>> {
+    match node_type(path)? {
+        NodeType::Evdev => show_evdev(path, format),
+        NodeType::Hidraw => show_hidraw(path, format),
+    }
+}
+
+/// The kernel device nodes the CLI supports
+enum NodeType {
+    Evdev,
+    Hidraw,
+}
+
+/// Return the type of the device node at the path, following symlinks.
+/// Returns an error for anything but an evdev or hidraw device node.
+fn node_type(path: &std::path::Path) -> Result<NodeType, Box<dyn Error>> {
+    let cpath = std::fs::canonicalize(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let devnode = cpath.to_string_lossy();
+    if devnode.starts_with("/dev/input/event") {
+        Ok(NodeType::Evdev)
     } else if devnode.starts_with("/dev/hidraw") {
-        show_hidraw(path)?
+        Ok(NodeType::Hidraw)
     } else {
-        panic!("Support for path {:?} is not implemented", path);
+        Err(format!(
+            "{}: unsupported path, expected an evdev node (/dev/input/event*) \
+             or a hidraw node (/dev/hidraw*)",
+            path.display()
+        )
+        .into())
+    }
+}
+
+/// Open the device node, with the path in any error message
+fn open(path: &std::path::Path) -> Result<File, Box<dyn Error>> {
+    File::open(path).map_err(|e| format!("{}: {e}", path.display()).into())
+}
+
+fn capture(
+    path: &std::path::PathBuf,
+    output: &Option<std::path::PathBuf>,
+) -> Result<(), Box<dyn Error>> {
+    let f = open(path)?;
+    let data = Capture::from_fd(OwnedFd::from(f))?.serialize();
+    match output {
+        Some(output) => std::fs::write(output, data)?,
+        None => println!("{data}"),
     }
     Ok(())
 }
 
-fn tree(paths: &Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
+/// Compare whodat's capabilities of the given evdev devices with their udev
+/// tags. Returns the number of devices where the two disagree, devices that
+/// fail to open are listed with their error but not counted.
+fn verify(paths: &[PathBuf], format: Format) -> Result<usize, Box<dyn Error>> {
+    let infos: Vec<VerifyInfo> = paths
+        .iter()
+        .map(|path| match EvdevDevice::from_path(path) {
+            Ok(device) => VerifyInfo::new(path, &device),
+            Err(e) => VerifyInfo::failed(path, &e),
+        })
+        .collect();
+
+    match format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(&infos)?),
+        Format::Text => {
+            for info in infos.iter() {
+                print_verify(info);
+            }
+        }
+    }
+
+    Ok(infos.iter().filter(|info| info.is_mismatch()).count())
+}
+
+/// Print the best-effort classification of the device with the given ids
+/// and name. Returns false if nothing could be determined about the device.
+fn lookup(
+    (vid, pid): (u16, u16),
+    name: &Option<String>,
+    format: Format,
+) -> Result<bool, Box<dyn Error>> {
+    let mut builder = Builder::new();
+    builder.usbid(vid, pid);
+    if let Some(name) = name {
+        builder.name(name);
+    }
+    let device = builder.build_physical()?;
+    // No capabilities means we know nothing, not that the device can't do anything
+    if device.capabilities().is_empty() {
+        eprintln!("{vid:04x}:{pid:04x}: insufficient information");
+        return Ok(false);
+    }
+
+    let info = LookupInfo {
+        vid,
+        pid,
+        device: ParentInfo::new(&device, None, &[]),
+        confidence: device.confidence(),
+    };
+    match format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(&info)?),
+        Format::Text => print_lookup(&info),
+    }
+
+    Ok(true)
+}
+
+/// Print the quirks for the given vid/pid, or all quirks if `None`.
+/// Returns the number of quirks printed.
+fn quirks(usbid: Option<(u16, u16)>, format: Format) -> Result<usize, Box<dyn Error>> {
+    let quirks: Vec<QuirkInfo> = Quirk::all()
+        .filter(|q| usbid.map_or(true, |(vid, pid)| q.vid() == vid && q.pid() == pid))
+        .map(QuirkInfo::from)
+        .collect();
+
+    match format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(&quirks)?),
+        Format::Text => {
+            for quirk in quirks.iter() {
+                print_quirk(quirk);
+            }
+        }
+    }
+
+    Ok(quirks.len())
+}
+
+/// Print the tree of the given devices. Returns the number of
+/// parent devices printed.
+fn tree(paths: &[PathBuf], only: &[Capability], format: Format) -> Result<usize, Box<dyn Error>> {
+    let tree = build_tree(paths)?;
+    print_tree(&tree, only, format)
+}
+
+/// Print the tree of the given devices, or all devices on the system if
+/// no paths are given, then print the tree again whenever a device is
+/// added or removed. This function only returns on error.
+///
+/// With explicit paths, devices added later are only shown if their device
+/// node is one of the paths, e.g. a device that is unplugged and plugged
+/// back in.
+///
+/// The output is plain text or JSON, we don't touch the terminal state so
+/// there is nothing to clean up when the user hits Ctrl-C.
+fn watch(paths: &[PathBuf], only: &[Capability], format: Format) -> Result<(), Box<dyn Error>> {
+    let mut tree = build_tree(paths)?;
+    if paths.is_empty() {
+        tree.attach_system()?;
+    }
+    // The monitor attaches every new device, so we compare device nodes
+    // against the canonical paths to drop the ones we weren't asked about
+    let devnodes: Vec<PathBuf> = paths
+        .iter()
+        .map(std::fs::canonicalize)
+        .collect::<Result<_, _>>()?;
+    let mut monitor = whodat::DeviceTree::monitor()?;
+    print_tree(&tree, only, format)?;
+
+    loop {
+        let mut pollfd = libc::pollfd {
+            fd: monitor.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let rc = unsafe { libc::poll(&mut pollfd, 1, -1) };
+        if rc < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(Box::new(err));
+        }
+
+        let mut changed = false;
+        while let Some(event) = monitor.next_event(&mut tree)? {
+            if let whodat::MonitorEvent::Added(idx) = event {
+                if !paths.is_empty() && !is_devnode_of(&tree, &idx, &devnodes) {
+                    tree.remove(&idx);
+                    continue;
+                }
+            }
+            if format == Format::Text {
+                match event {
+                    whodat::MonitorEvent::Added(idx) => println!("# added {idx:?}"),
+                    whodat::MonitorEvent::Removed(idx) => println!("# removed {idx:?}"),
+                }
+            }
+            changed = true;
+        }
+        if changed {
+            print_tree(&tree, only, format)?;
+        }
+    }
+}
+
+/// Returns true if the kernel device with the given index has one of the
+/// given device nodes
+fn is_devnode_of(
+    tree: &whodat::DeviceTree,
+    idx: &whodat::DeviceIndex,
+    devnodes: &[PathBuf],
+) -> bool {
+    let devnode = match tree.get_device(idx) {
+        Some(AttachedDevice::Evdev(d)) => d.devnode(),
+        Some(AttachedDevice::Hidraw(d)) => d.devnode(),
+        _ => return false,
+    };
+    devnode.as_ref().is_some_and(|node| devnodes.contains(node))
+}
+
+/// Attach the devices at the given paths to a new tree
+fn build_tree(paths: &[PathBuf]) -> Result<whodat::DeviceTree, Box<dyn Error>> {
     let mut tree = whodat::DeviceTree::new();
 
     for path in paths {
-        let cpath = std::fs::canonicalize(path)?;
-        let f = File::open(path)?;
-        let devnode = cpath.as_os_str().to_str().unwrap();
-        if devnode.starts_with("/dev/input/") {
-            tree.attach_evdev(OwnedFd::from(f))?;
-        } else {
-            panic!("Support for path {:?} is not implemented", path);
-        }
-    }
-
-    for node in tree.iter() {
-        match node {
-            AttachedDevice::Parent(parent) => {
-                print_parent(&parent, "");
-                println!("    children:");
-                for child in parent.iter() {
-                    match tree.get_device(child).expect("Device disappeared?") {
-                        AttachedDevice::Evdev(evdev) => {
-                            print_evdev(&evdev, "    ");
-                        },
-                        _ => {},
-                    }
-                }
+        let kind = node_type(path)?;
+        let f = OwnedFd::from(open(path)?);
+        match kind {
+            NodeType::Evdev => tree.attach_evdev(f)?,
+            NodeType::Hidraw => tree.attach_hidraw(f)?,
+        };
+    }
+
+    Ok(tree)
+}
+
+/// Print the parent devices in the tree and their children. Returns the
+/// number of parent devices printed.
+fn print_tree(
+    tree: &whodat::DeviceTree,
+    only: &[Capability],
+    format: Format,
+) -> Result<usize, Box<dyn Error>> {
+    let parents: Vec<ParentInfo> = tree
+        .iter()
+        .filter_map(|node| node.as_parent())
+        .filter(|parent| has_any(*parent, only))
+        .map(|parent| ParentInfo::new(parent, Some(tree), only))
+        .collect();
 
-            },
-            _ => {},
+    match format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(&parents)?),
+        Format::Text => {
+            for parent in parents.iter() {
+                print_parent(parent, "");
+            }
         }
     }
 
-    Ok(())
+    Ok(parents.len())
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
+fn main() {
     let cli = Cli::parse();
 
+    // Print errors ourselves, returning them from main prints their Debug
+    // representation
+    if let Err(e) = run(&cli) {
+        eprintln!("Error: {e}");
+        std::process::exit(EXIT_ERROR);
+    }
+}
+
+fn run(cli: &Cli) -> Result<(), Box<dyn Error>> {
     match &cli.command {
-        Commands::Show { path } => show(&path)?,
-        Commands::Tree { paths } => tree(paths)?,
+        Commands::Show { path, replay } => match (path, replay) {
+            (_, Some(replay)) => show_replay(replay, cli.format)?,
+            (Some(path), None) => show(path, cli.format)?,
+            (None, None) => unreachable!("clap requires path or --replay"),
+        },
+        Commands::Capture { path, output } => capture(path, output)?,
+        Commands::Tree { paths, only, watch: true } => watch(paths, only, cli.format)?,
+        Commands::Tree { paths, only, watch: false } => {
+            if tree(paths, only, cli.format)? == 0 && !only.is_empty() {
+                std::process::exit(EXIT_NO_MATCH);
+            }
+        }
+        Commands::Verify { paths } => {
+            if verify(paths, cli.format)? > 0 {
+                std::process::exit(EXIT_MISMATCH);
+            }
+        }
+        Commands::Lookup { usbid, name } => {
+            if !lookup(*usbid, name, cli.format)? {
+                std::process::exit(EXIT_NO_MATCH);
+            }
+        }
+        Commands::Quirks { command: QuirksCommands::List } => {
+            quirks(None, cli.format)?;
+        }
+        Commands::Quirks { command: QuirksCommands::Lookup { usbid } } => {
+            if quirks(Some(*usbid), cli.format)? == 0 {
+                std::process::exit(EXIT_NO_MATCH);
+            }
+        }
     }
 
     Ok(())
@@ -0,0 +1,357 @@
+use crate::*;
+
+use std::os::fd::OwnedFd;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// The [`Builder`] creates a device from whatever information the caller
+/// has available, for example where the caller cannot open the device node
+/// but can read sysfs.
+///
+/// ```no_run
+/// use std::path::Path;
+/// use whodat::{Builder, HasCapability};
+///
+/// let device = Builder::new()
+///     .sysfs_path(Path::new("/sys/class/input/event0"))
+///     .build()
+///     .unwrap();
+/// println!("{} has capabilities {:?}", device.name(), device.capabilities());
+/// ```
+///
+/// The order in which information is given determines the priority: later
+/// information overrides earlier information for the same field. Here the
+/// name overrides the name found via the sysfs path:
+///
+/// ```no_run
+/// # use std::path::Path;
+/// # use whodat::Builder;
+/// let device = Builder::new()
+///     .sysfs_path(Path::new("/sys/class/input/event0"))
+///     .name("My Device")
+///     .build()
+///     .unwrap();
+/// assert_eq!(device.name(), "My Device");
+/// ```
+///
+/// Had the name been given first, the name found via the sysfs path would
+/// have overridden it.
+#[derive(Debug, Default, Clone)]
+pub struct Builder {
+    /// All information in the order it was given
+    inputs: Vec<Input>,
+}
+
+/// One piece of information given to the [`Builder`]
+#[derive(Debug, Clone)]
+enum Input {
+    SysfsPath(PathBuf),
+    /// Shared so the builder can be cloned, see [`Builder::build_async`]
+    EvdevFd(Arc<OwnedFd>),
+    HidrawFd(Arc<OwnedFd>),
+    Name(String),
+    Usbid(u16, u16),
+    Bluetooth([u8; 6]),
+    BluetoothId(u16, u16),
+    UdevProperties(Vec<(String, String)>),
+    Phys(String),
+}
+
+impl Builder {
+    /// Create a new builder without any information.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build the device from the sysfs path of an input device, e.g.
+    /// `/sys/class/input/event0`. Building from a sysfs path does not require
+    /// access to the device node, all information is read from sysfs and
+    /// the udev properties.
+    ///
+    /// Everything found via the sysfs path overrides any information given
+    /// to this builder before the sysfs path.
+    ///
+    /// Without the `udev` feature, building a device from a sysfs path fails
+    /// with [`Error::InsufficientInformation`].
+    pub fn sysfs_path(&mut self, path: &Path) -> &mut Self {
+        self.inputs.push(Input::SysfsPath(path.to_path_buf()));
+        self
+    }
+
+    /// Build the device from an fd to its evdev device node. The fd must be
+    /// ready for `ioctl()`, no data is read or written on this fd. Like a
+    /// sysfs path, everything found via the fd overrides any information
+    /// given to this builder before the fd.
+    ///
+    /// Without the `udev` feature, building a device from an fd fails
+    /// with [`Error::InsufficientInformation`].
+    pub fn evdev_fd(&mut self, fd: OwnedFd) -> &mut Self {
+        self.inputs.push(Input::EvdevFd(Arc::new(fd)));
+        self
+    }
+
+    /// Set an fd to the hidraw device node of the device, see
+    /// [`Builder::build_hidraw`]. The fd must be ready for `ioctl()`, no
+    /// data is read or written on this fd.
+    ///
+    /// Where both an evdev device (e.g. via [`Builder::evdev_fd`]) and a hidraw
+    /// fd are given, these are two kernel devices of the same physical device,
+    /// see [`Builder::build_physical`]. The hidraw fd does not affect
+    /// [`Builder::build`] and none of the other information given to this
+    /// builder affects the hidraw device.
+    pub fn hidraw_fd(&mut self, fd: OwnedFd) -> &mut Self {
+        self.inputs.push(Input::HidrawFd(Arc::new(fd)));
+        self
+    }
+
+    /// Set the name of the device. This name overrides the name of the
+    /// device found via any information given to this builder before the name.
+    ///
+    /// Where no other information gives us the device's capabilities,
+    /// the capabilities are guessed from the name. Such a guess may be wrong,
+    /// see [`PhysicalDevice::confidence`].
+    pub fn name(&mut self, name: &str) -> &mut Self {
+        self.inputs.push(Input::Name(name.to_string()));
+        self
+    }
+
+    /// Set the USB vendor and product id of the device. These ids override
+    /// the ids of the device found via any information given to this
+    /// builder before the ids and are used to look up the device in the
+    /// database of well-known devices. The device is a [`BusType::Usb`] device.
+    pub fn usbid(&mut self, vid: u16, pid: u16) -> &mut Self {
+        self.inputs.push(Input::Usbid(vid, pid));
+        self
+    }
+
+    /// Set the Bluetooth address of the device. A device with a Bluetooth
+    /// address is a [`BusType::Bluetooth`] device, regardless of the bus type
+    /// found via any information given to this builder before the address.
+    /// The address is available as the device's [`EvdevDevice::uniq`].
+    pub fn bluetooth(&mut self, address: [u8; 6]) -> &mut Self {
+        self.inputs.push(Input::Bluetooth(address));
+        self
+    }
+
+    /// Set the vendor and product id the device advertises over Bluetooth.
+    /// Some devices, e.g. some gaming controllers, use different ids over
+    /// Bluetooth than over USB so the database of well-known devices may
+    /// only match one of them.
+    ///
+    /// Like [`Builder::usbid`] the ids override any ids given before,
+    /// the device is a [`BusType::Bluetooth`] device.
+    pub fn bluetooth_id(&mut self, vid: u16, pid: u16) -> &mut Self {
+        self.inputs.push(Input::BluetoothId(vid, pid));
+        self
+    }
+
+    /// Set the udev properties of the device as name/value pairs, e.g.
+    /// `("ID_INPUT_TOUCHPAD", "1")`, for a caller that already has them.
+    /// The capabilities are derived from these properties exactly as from the
+    /// properties of a kernel device, no udev access is required.
+    ///
+    /// ```
+    /// use whodat::{Builder, Capability, HasCapability};
+    ///
+    /// let device = Builder::new()
+    ///     .name("SynPS/2 Synaptics TouchPad")
+    ///     .udev_properties(vec![
+    ///         ("ID_INPUT".to_string(), "1".to_string()),
+    ///         ("ID_INPUT_TOUCHPAD".to_string(), "1".to_string()),
+    ///     ])
+    ///     .build()
+    ///     .unwrap();
+    /// assert!(device.has(Capability::Touchpad));
+    /// ```
+    ///
+    /// These properties replace all udev properties of the device found via
+    /// any information given to this builder before the properties. Without
+    /// a name or ids, the device has no name.
+    pub fn udev_properties(&mut self, props: Vec<(String, String)>) -> &mut Self {
+        self.inputs.push(Input::UdevProperties(props));
+        self
+    }
+
+    /// Set the physical topology path of the device as reported by the
+    /// kernel, e.g. `usb-0000:00:14.0-2/input0`, see [`EvdevDevice::phys`].
+    /// Devices built without a sysfs path or fd share a [`PhysicalDevice`]
+    /// if their physical paths only differ in the `/inputN` suffix.
+    pub fn phys(&mut self, phys: &str) -> &mut Self {
+        self.inputs.push(Input::Phys(phys.to_string()));
+        self
+    }
+
+    /// Build the device from the information given to this builder on a
+    /// blocking thread pool, see [`Builder::build`]. Building a device may
+    /// require a udev scan, this avoids blocking an async executor for
+    /// the duration of that scan.
+    ///
+    /// This function is only available with the `async` feature.
+    #[cfg(feature = "async")]
+    pub fn build_async(
+        &self,
+    ) -> impl std::future::Future<Output = Result<EvdevDevice, Error>> {
+        let builder = self.clone();
+        async_std::task::spawn_blocking(move || builder.build())
+    }
+
+    /// Build the device from the information given to this builder, in the
+    /// order it was given.
+    ///
+    /// Without a sysfs path the device is classified from its name and the
+    /// internal database of well-known devices only, no device needs to be
+    /// present. Such a classification is a best guess, see
+    /// [`EvdevDevice::confidence`]. Where neither gives us anything, the
+    /// device's capabilities are unknown, see [`HasCapability::has_capability`].
+    ///
+    /// Returns [`Error::InsufficientInformation`] if neither a sysfs path,
+    /// a name, a vendor and product id nor udev properties were given.
+    pub fn build(&self) -> Result<EvdevDevice, Error> {
+        // The last sysfs path or fd overrides everything given before it, so
+        // we start from there
+        let start = self
+            .inputs
+            .iter()
+            .rposition(|i| matches!(i, Input::SysfsPath(_) | Input::EvdevFd(_)));
+        let (mut device, inputs) = match start {
+            Some(idx) => match &self.inputs[idx] {
+                Input::SysfsPath(path) => (device_from_syspath(path)?, &self.inputs[idx + 1..]),
+                Input::EvdevFd(fd) => (device_from_fd(fd)?, &self.inputs[idx + 1..]),
+                _ => unreachable!(),
+            },
+            None => {
+                let name = self.inputs.iter().find_map(|i| match i {
+                    Input::Name(name) => Some(name.as_str()),
+                    _ => None,
+                });
+                let has_ids = self.inputs.iter().any(|i| {
+                    matches!(
+                        i,
+                        Input::Usbid(..) | Input::BluetoothId(..) | Input::UdevProperties(_)
+                    )
+                });
+                match (name, has_ids) {
+                    (Some(name), _) => (EvdevDevice::from_name(name), &self.inputs[..]),
+                    // The ids or udev properties alone are enough to classify
+                    // the device
+                    (None, true) => (EvdevDevice::from_name(""), &self.inputs[..]),
+                    (None, false) => return Err(Error::InsufficientInformation),
+                }
+            }
+        };
+
+        for input in inputs {
+            match input {
+                Input::SysfsPath(_) | Input::EvdevFd(_) => unreachable!(),
+                Input::HidrawFd(_) => {}
+                Input::Name(name) => {
+                    if name.as_str() != device.name() {
+                        device.set_name(name);
+                    }
+                }
+                Input::Usbid(vid, pid) => device.set_id(BusType::Usb, *vid, *pid),
+                Input::BluetoothId(vid, pid) => device.set_id(BusType::Bluetooth, *vid, *pid),
+                Input::UdevProperties(props) => device.set_udev_properties(props),
+                Input::Phys(phys) => device.set_phys(phys),
+                Input::Bluetooth(address) => {
+                    // The kernel reports the Bluetooth address as uniq
+                    let uniq: Vec<String> = address.iter().map(|b| format!("{b:02x}")).collect();
+                    device.set_uniq(&uniq.join(":"));
+                    if device.bustype() != BusType::Bluetooth {
+                        device.set_id(BusType::Bluetooth, device.vid(), device.pid());
+                    }
+                }
+            }
+        }
+
+        Ok(device)
+    }
+
+    /// Build the hidraw device from the last fd given with
+    /// [`Builder::hidraw_fd`]. The device is classified from its HID report
+    /// descriptor, see [`HidrawDevice::capabilities`].
+    ///
+    /// Returns [`Error::InsufficientInformation`] if no hidraw fd was given
+    /// or whodat was built without the `udev` feature.
+    pub fn build_hidraw(&self) -> Result<HidrawDevice, Error> {
+        let fd = self
+            .inputs
+            .iter()
+            .rev()
+            .find_map(|i| match i {
+                Input::HidrawFd(fd) => Some(fd),
+                _ => None,
+            })
+            .ok_or(Error::InsufficientInformation)?;
+        hidraw_from_fd(fd)
+    }
+
+    /// Build the device like [`Builder::build`] and return the
+    /// [`PhysicalDevice`] for this device, e.g. to get the
+    /// [`PhysicalDevice::device_type`] of a device from its ids alone.
+    ///
+    /// ```
+    /// use whodat::{Builder, DeviceType};
+    ///
+    /// // Sony DualSense
+    /// let device = Builder::new().usbid(0x054c, 0x0ce6).build_physical().unwrap();
+    /// assert_eq!(device.device_type(), Some(DeviceType::Gamepad));
+    /// ```
+    ///
+    /// Where a hidraw fd was given (see [`Builder::hidraw_fd`]), the hidraw
+    /// device is part of the same physical device as the evdev device.
+    /// With only a hidraw fd, the physical device is built from the
+    /// hidraw device alone.
+    ///
+    /// The returned device has no children and is not attached to any
+    /// [`DeviceTree`].
+    pub fn build_physical(&self) -> Result<PhysicalDevice, Error> {
+        let has_hidraw = self.inputs.iter().any(|i| matches!(i, Input::HidrawFd(_)));
+        let evdev = match self.build() {
+            Ok(device) => Some(AttachedDevice::Evdev(device)),
+            Err(Error::InsufficientInformation) if has_hidraw => None,
+            Err(e) => return Err(e),
+        };
+        let hidraw = if has_hidraw {
+            Some(AttachedDevice::Hidraw(self.build_hidraw()?))
+        } else {
+            None
+        };
+
+        let mut parent = PhysicalDevice::new();
+        for device in evdev.iter().chain(hidraw.iter()) {
+            parent.merge_capabilities(device);
+        }
+        Ok(parent)
+    }
+}
+
+#[cfg(feature = "udev")]
+fn device_from_syspath(path: &Path) -> Result<EvdevDevice, Error> {
+    EvdevDevice::from_syspath(path)
+}
+
+#[cfg(not(feature = "udev"))]
+fn device_from_syspath(_path: &Path) -> Result<EvdevDevice, Error> {
+    Err(Error::InsufficientInformation)
+}
+
+#[cfg(feature = "udev")]
+fn device_from_fd(fd: &OwnedFd) -> Result<EvdevDevice, Error> {
+    EvdevDevice::from_fd(fd.try_clone()?)
+}
+
+#[cfg(not(feature = "udev"))]
+fn device_from_fd(_fd: &OwnedFd) -> Result<EvdevDevice, Error> {
+    Err(Error::InsufficientInformation)
+}
+
+#[cfg(feature = "udev")]
+fn hidraw_from_fd(fd: &OwnedFd) -> Result<HidrawDevice, Error> {
+    HidrawDevice::from_fd(fd.try_clone()?)
+}
+
+#[cfg(not(feature = "udev"))]
+fn hidraw_from_fd(_fd: &OwnedFd) -> Result<HidrawDevice, Error> {
+    Err(Error::InsufficientInformation)
+}
@@ -0,0 +1,352 @@
+use crate::*;
+
+#[cfg(feature = "udev")]
+use evdev_device::KernelEvdev;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// The [`EvdevSource`] trait provides the raw information about an evdev
+/// device that whodat classifies a device from, see [`EvdevDevice::from_source`].
+///
+/// This is implemented by the kernel device itself (see [`EvdevDevice::from_fd`])
+/// and by a [`Capture`] of a kernel device.
+pub trait EvdevSource {
+    /// The device's name as advertised by the kernel, empty if the device
+    /// does not have a name
+    fn name(&self) -> String;
+    /// The raw bus type, vendor id and product id
+    fn input_id(&self) -> (u16, u16, u16);
+    /// The product version, typically the firmware revision
+    fn version(&self) -> u16 {
+        0
+    }
+    /// The unique identifier (`EVIOCGUNIQ`), if any
+    fn uniq(&self) -> Option<String> {
+        None
+    }
+    /// The physical topology path (`EVIOCGPHYS`), if any
+    fn phys(&self) -> Option<String> {
+        None
+    }
+    /// The kernel driver bound to the device, e.g. `hid-generic`, if known
+    fn driver(&self) -> Option<String> {
+        None
+    }
+    /// The `ID_INPUT_*` udev properties set on this device
+    fn udev_properties(&self) -> Vec<String>;
+    /// All udev properties of this device and their values
+    fn all_udev_properties(&self) -> HashMap<String, String> {
+        HashMap::new()
+    }
+    /// The raw `INPUT_PROP_*` properties
+    fn input_properties(&self) -> Vec<u16>;
+    /// The supported `EV_KEY` codes
+    fn keys(&self) -> Vec<u16>;
+    /// The supported `EV_ABS` codes
+    fn abs_axes(&self) -> Vec<u16>;
+    /// The supported `EV_REL` codes
+    fn rel_axes(&self) -> Vec<u16>;
+    /// The range and resolution of the supported `EV_ABS` codes, if known
+    fn abs_info(&self) -> HashMap<u16, AbsResolution> {
+        HashMap::new()
+    }
+    /// The supported `EV_SW` codes
+    fn switches(&self) -> Vec<u16> {
+        Vec::new()
+    }
+    /// The physical size in mm, if known
+    fn size_mm(&self) -> Option<(u32, u32)>;
+    /// The device node, if any
+    fn devnode(&self) -> Option<PathBuf>;
+    /// The sysfs path, if any
+    fn sysfs_path(&self) -> Option<PathBuf>;
+}
+
+/// A [`Capture`] is a snapshot of everything whodat reads from an evdev device.
+/// A capture can be saved on one machine and replayed on another, the
+/// [`EvdevDevice`] created from the capture is classified identically to the
+/// device the capture was taken from.
+///
+/// ```no_run
+/// use std::fs::File;
+/// use std::os::fd::OwnedFd;
+/// use whodat::{Capture, EvdevDevice};
+///
+/// let f = File::open("/dev/input/event0").unwrap();
+/// let data = Capture::from_fd(OwnedFd::from(f)).unwrap().serialize();
+///
+/// // possibly on a different machine
+/// let capture = Capture::deserialize(&data).unwrap();
+/// let device = EvdevDevice::from_source(&capture);
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Capture {
+    name: String,
+    bustype: u16,
+    vid: u16,
+    pid: u16,
+    #[serde(default)]
+    version: u16,
+    #[serde(default)]
+    uniq: Option<String>,
+    #[serde(default)]
+    phys: Option<String>,
+    #[serde(default)]
+    driver: Option<String>,
+    udev_properties: Vec<String>,
+    #[serde(default)]
+    all_udev_properties: HashMap<String, String>,
+    input_properties: Vec<u16>,
+    keys: Vec<u16>,
+    abs_axes: Vec<u16>,
+    rel_axes: Vec<u16>,
+    #[serde(default)]
+    abs_info: HashMap<u16, AbsResolution>,
+    #[serde(default)]
+    switches: Vec<u16>,
+    size_mm: Option<(u32, u32)>,
+    devnode: Option<PathBuf>,
+    sysfs: Option<PathBuf>,
+}
+
+impl Capture {
+    /// Capture the device that the fd points to.
+    /// The fd must be ready for `ioctl()` no data is read or written on this fd.
+    #[cfg(feature = "udev")]
+    pub fn from_fd(fd: OwnedFd) -> Result<Capture, Error> {
+        let source = KernelEvdev::from_fd(fd)?;
+        Ok(Self::from_source(&source))
+    }
+
+    /// Capture all information the source provides.
+    pub fn from_source(source: &dyn EvdevSource) -> Capture {
+        let (bustype, vid, pid) = source.input_id();
+        Capture {
+            name: source.name(),
+            bustype,
+            vid,
+            pid,
+            version: source.version(),
+            uniq: source.uniq(),
+            phys: source.phys(),
+            driver: source.driver(),
+            udev_properties: source.udev_properties(),
+            all_udev_properties: source.all_udev_properties(),
+            input_properties: source.input_properties(),
+            keys: source.keys(),
+            abs_axes: source.abs_axes(),
+            rel_axes: source.rel_axes(),
+            abs_info: source.abs_info(),
+            switches: source.switches(),
+            size_mm: source.size_mm(),
+            devnode: source.devnode(),
+            sysfs: source.sysfs_path(),
+        }
+    }
+
+    /// Serialize this capture into a versioned JSON format, see
+    /// [`Capture::deserialize`].
+    pub fn serialize(&self) -> String {
+        serialize::to_string(self)
+    }
+
+    /// Reconstruct a capture from the data returned by [`Capture::serialize`].
+    /// Data serialized with a different [`FORMAT_VERSION`] is rejected.
+    pub fn deserialize(data: &str) -> Result<Capture, Box<dyn StdError>> {
+        serialize::from_str(data)
+    }
+}
+
+impl EvdevSource for Capture {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn input_id(&self) -> (u16, u16, u16) {
+        (self.bustype, self.vid, self.pid)
+    }
+
+    fn version(&self) -> u16 {
+        self.version
+    }
+
+    fn uniq(&self) -> Option<String> {
+        self.uniq.clone()
+    }
+
+    fn phys(&self) -> Option<String> {
+        self.phys.clone()
+    }
+
+    fn driver(&self) -> Option<String> {
+        self.driver.clone()
+    }
+
+    fn udev_properties(&self) -> Vec<String> {
+        self.udev_properties.clone()
+    }
+
+    fn all_udev_properties(&self) -> HashMap<String, String> {
+        self.all_udev_properties.clone()
+    }
+
+    fn input_properties(&self) -> Vec<u16> {
+        self.input_properties.clone()
+    }
+
+    fn keys(&self) -> Vec<u16> {
+        self.keys.clone()
+    }
+
+    fn abs_axes(&self) -> Vec<u16> {
+        self.abs_axes.clone()
+    }
+
+    fn rel_axes(&self) -> Vec<u16> {
+        self.rel_axes.clone()
+    }
+
+    fn abs_info(&self) -> HashMap<u16, AbsResolution> {
+        self.abs_info.clone()
+    }
+
+    fn switches(&self) -> Vec<u16> {
+        self.switches.clone()
+    }
+
+    fn size_mm(&self) -> Option<(u32, u32)> {
+        self.size_mm
+    }
+
+    fn devnode(&self) -> Option<PathBuf> {
+        self.devnode.clone()
+    }
+
+    fn sysfs_path(&self) -> Option<PathBuf> {
+        self.sysfs.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A clickpad as the kernel and udev describe it
+    struct Clickpad;
+
+    impl EvdevSource for Clickpad {
+        fn name(&self) -> String {
+            "SYNA8004:00 06CB:CD8B Touchpad".to_string()
+        }
+
+        fn input_id(&self) -> (u16, u16, u16) {
+            (0x18, 0x06cb, 0xcd8b)
+        }
+
+        fn phys(&self) -> Option<String> {
+            Some("i2c-SYNA8004:00".to_string())
+        }
+
+        fn driver(&self) -> Option<String> {
+            Some("hid-multitouch".to_string())
+        }
+
+        fn udev_properties(&self) -> Vec<String> {
+            vec!["ID_INPUT".to_string(), "ID_INPUT_TOUCHPAD".to_string()]
+        }
+
+        fn all_udev_properties(&self) -> HashMap<String, String> {
+            [
+                ("ID_INPUT", "1"),
+                ("ID_INPUT_TOUCHPAD", "1"),
+                ("ID_PATH", "pci-0000:00:15.1-platform-i2c_designware.1"),
+            ]
+            .iter()
+            .map(|(n, v)| (n.to_string(), v.to_string()))
+            .collect()
+        }
+
+        fn input_properties(&self) -> Vec<u16> {
+            // INPUT_PROP_POINTER, INPUT_PROP_BUTTONPAD
+            vec![0x00, 0x02]
+        }
+
+        fn keys(&self) -> Vec<u16> {
+            // BTN_LEFT, BTN_TOOL_FINGER, BTN_TOUCH
+            vec![0x110, 0x145, 0x14a]
+        }
+
+        fn abs_axes(&self) -> Vec<u16> {
+            vec![0x00, 0x01, 0x2f, 0x35, 0x36, 0x39]
+        }
+
+        fn rel_axes(&self) -> Vec<u16> {
+            Vec::new()
+        }
+
+        fn abs_info(&self) -> HashMap<u16, AbsResolution> {
+            let axis = |maximum: i32| AbsResolution {
+                minimum: 0,
+                maximum,
+                resolution: 12,
+                fuzz: 0,
+                flat: 0,
+            };
+            HashMap::from([(0x00, axis(1224)), (0x01, axis(804))])
+        }
+
+        fn size_mm(&self) -> Option<(u32, u32)> {
+            None
+        }
+
+        fn devnode(&self) -> Option<PathBuf> {
+            Some(PathBuf::from("/dev/input/event7"))
+        }
+
+        fn sysfs_path(&self) -> Option<PathBuf> {
+            None
+        }
+    }
+
+    fn abstract_type(device: &EvdevDevice) -> Option<AbstractType> {
+        let mut parent = PhysicalDevice::new();
+        parent.merge_capabilities(&AttachedDevice::Evdev(device.clone()));
+        parent.abstract_types().first().cloned()
+    }
+
+    #[test]
+    fn test_replay() {
+        let original = EvdevDevice::from_source(&Clickpad);
+        let data = Capture::from_source(&Clickpad).serialize();
+        let replayed = EvdevDevice::from_source(&Capture::deserialize(&data).unwrap());
+
+        assert_eq!(replayed.name(), original.name());
+        assert_eq!(replayed.capabilities(), original.capabilities());
+        assert_eq!(replayed.sources(), original.sources());
+        assert_eq!(replayed.capability_sources(), original.capability_sources());
+        assert_eq!(
+            replayed.udev_property("ID_PATH"),
+            original.udev_property("ID_PATH")
+        );
+        assert_eq!(replayed.size_mm(), original.size_mm());
+        assert!(matches!(
+            abstract_type(&replayed),
+            Some(AbstractType::Pointer)
+        ));
+        assert_eq!(
+            format!("{:?}", abstract_type(&replayed)),
+            format!("{:?}", abstract_type(&original))
+        );
+    }
+
+    #[test]
+    fn test_replay_wrong_version() {
+        let data = Capture::from_source(&Clickpad).serialize();
+        let current = format!("\"version\":{FORMAT_VERSION}");
+        assert!(data.contains(&current));
+
+        let data = data.replace(&current, &format!("\"version\":{}", FORMAT_VERSION + 1));
+        assert!(Capture::deserialize(&data).is_err());
+        assert!(Capture::deserialize("{\"device\":{}}").is_err());
+    }
+}
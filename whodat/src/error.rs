@@ -0,0 +1,65 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// The errors returned by this crate where a caller may want to
+/// distinguish between them.
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum Error {
+    /// The path or device is not an input device this crate can handle
+    NotAnInputDevice(PathBuf),
+    /// The device was removed before we could look it up, e.g. between
+    /// opening the device node and querying udev
+    DeviceGone,
+    /// Accessing the device failed
+    Io(io::Error),
+    /// Querying udev failed
+    Udev(io::Error),
+    /// The device does not support the named ioctl
+    UnsupportedIoctl(&'static str),
+    /// The information given to the [`Builder`](crate::Builder) is not
+    /// sufficient to build a device
+    InsufficientInformation,
+}
+
+impl Error {
+    /// Return the error for the most recent failed ioctl with the given name.
+    pub(crate) fn last_ioctl_error(ioctl: &'static str) -> Error {
+        let error = io::Error::last_os_error();
+        match error.raw_os_error() {
+            Some(libc::ENOTTY) | Some(libc::EINVAL) => Error::UnsupportedIoctl(ioctl),
+            _ => Error::Io(error),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotAnInputDevice(path) => write!(f, "{path:?} is not an input device"),
+            Error::DeviceGone => write!(f, "The device has been removed"),
+            Error::Io(e) => write!(f, "I/O error: {e}"),
+            Error::Udev(e) => write!(f, "udev error: {e}"),
+            Error::UnsupportedIoctl(ioctl) => write!(f, "The device does not support {ioctl}"),
+            Error::InsufficientInformation => {
+                write!(f, "Insufficient information to build a device")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) | Error::Udev(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
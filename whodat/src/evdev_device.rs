@@ -1,95 +1,432 @@
 use crate::*;
 
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
 /// The [`EvdevDevice`] struct represents a single kernel device and
 /// the queryable information about this device.
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EvdevDevice {
     /// Attachment in the [`DeviceTree`]
+    #[serde(skip, default = "Node::new")]
     node: Node,
     name: String,
+    bustype: BusType,
     vid: u16,
     pid: u16,
+    #[serde(default)]
+    version: u16,
+    #[serde(default)]
+    uniq: Option<String>,
+    #[serde(default)]
+    phys: Option<String>,
+    /// The kernel driver, see [`EvdevDevice::driver`]
+    #[serde(default)]
+    driver: Option<String>,
     udev_properties: Vec<String>,
+    /// All udev properties, not just the `ID_INPUT_*` ones
+    #[serde(default)]
+    all_udev_properties: HashMap<String, String>,
+    input_properties: Vec<InputProp>,
+    /// The supported EV_KEY, EV_ABS and EV_REL codes
+    keys: Vec<u16>,
+    abs_axes: Vec<u16>,
+    rel_axes: Vec<u16>,
+    /// The EVIOCGABS information for each EV_ABS code, if known
+    #[serde(default)]
+    abs_info: HashMap<u16, AbsResolution>,
+    /// The supported EV_SW codes
+    #[serde(default)]
+    switches: Vec<u16>,
     capabilities: Vec<Capability>,
+    sources: Vec<Source>,
+    /// The most reliable source of each capability
+    #[serde(default)]
+    capability_sources: Vec<(Capability, Source)>,
     devnode: Option<PathBuf>,
-    sysfs: PathBuf,
+    sysfs: Option<PathBuf>,
+    size_mm: Option<(u32, u32)>,
+    /// The quirks loaded at runtime for the tree this device is attached
+    /// to, see [`DeviceTree::with_quirks`]
+    #[serde(skip)]
+    quirks: Arc<Vec<Quirk>>,
+}
+
+/// Two [`EvdevDevice`]s are equal if they have the same [`DeviceIndex`], i.e. a
+/// clone is equal to the device it was cloned from.
+impl PartialEq for EvdevDevice {
+    fn eq(&self, other: &Self) -> bool {
+        self.index() == other.index()
+    }
 }
 
 impl HasParent for EvdevDevice {
-    fn parent(&self) -> DeviceIndex {
-        match self.node.parent {
-            Some(index) => index.clone(),
-            None => {
-                panic!("No parent set for {:?}, missing set_parent() call", self);
-            }
-        }
+    fn try_parent(&self) -> Option<DeviceIndex> {
+        self.node.parent
     }
 }
 
 impl HasCapability for EvdevDevice {
     fn capabilities(&self) -> Vec<Capability> {
-        self.capabilities.clone()
+        let mut capabilities = self.capabilities.clone();
+        capabilities.sort();
+        capabilities.dedup();
+        capabilities
+    }
+
+    /// A device is only unknown if none of the [`Source`]s told us
+    /// anything about it, a device with sources but without capabilities
+    /// is known to have none.
+    fn has_capability(&self, cap: Capability) -> Option<bool> {
+        if self.sources.is_empty() {
+            None
+        } else {
+            Some(self.capabilities.contains(&cap))
+        }
+    }
+}
+
+impl Device for EvdevDevice {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn physical_parent(&self) -> Option<DeviceIndex> {
+        self.try_parent()
     }
 }
 
 impl<'a> EvdevDevice {
     /// Return a new [`EvdevDevice`] based on the device that the fd points to.
     /// The fd must be ready for `ioctl()` no data is read or written on this fd.
-    pub fn from_fd(fd: OwnedFd) -> Result<EvdevDevice, Box<dyn Error>> {
-        // Get st_rdev from the fd so we can later look this up with udev
-        let f = File::from(fd);
-        let meta = f.metadata()?;
-        let rdev = meta.st_rdev();
+    ///
+    /// Returns [`Error::DeviceGone`] if the device was removed since the fd
+    /// was opened.
+    #[cfg(feature = "udev")]
+    pub fn from_fd(fd: OwnedFd) -> Result<EvdevDevice, Error> {
+        let source = KernelEvdev::from_fd(fd)?;
+        let device = Self::from_source(&source);
+        log::debug!(
+            "{:?} ({}): capabilities {:?} from {:?}",
+            device.sysfs_path(),
+            device.name(),
+            device.capabilities(),
+            device.sources()
+        );
+        Ok(device)
+    }
 
-        // Now fetch out the udev properties
-        let udev_properties: Vec<String> = Vec::new();
-        let mut e = udev::Enumerator::new()?;
-        e.match_subsystem("input")?;
-        let mut devices = e.scan_devices()?;
-        let device: Option<udev::Device> = devices.find_map(|d| match &d.devnum() {
-            Some(num) if *num == rdev => Some(d),
-            _ => None,
-        });
+    /// Return a new [`EvdevDevice`] based on the information provided by
+    /// the source, e.g. a [`Capture`] of a device on a different machine.
+    /// The capabilities are evaluated exactly as for the kernel device.
+    pub fn from_source(source: &dyn EvdevSource) -> EvdevDevice {
+        let (bustype, vid, pid) = source.input_id();
+        let version = source.version();
+        let name = source.name();
+        let udev_properties = source.udev_properties();
+        let input_properties: Vec<InputProp> = source
+            .input_properties()
+            .into_iter()
+            .filter_map(InputProp::from_raw)
+            .collect();
+        let keys = source.keys();
+        let abs_axes = source.abs_axes();
+        let rel_axes = source.rel_axes();
+        let driver = source.driver();
+        let (capabilities, sources, capability_sources) = capabilities_from(
+            &udev_properties,
+            &input_properties,
+            &name,
+            (vid, pid, version),
+            driver.as_deref(),
+            &keys,
+            &abs_axes,
+            &rel_axes,
+            GamepadProfile::from_codes(&keys, &abs_axes),
+            &[],
+        );
+
+        Self {
+            node: Node::new(),
+            name,
+            bustype: BusType::from_raw(bustype),
+            vid,
+            pid,
+            version,
+            uniq: source.uniq(),
+            phys: source.phys(),
+            driver,
+            udev_properties,
+            all_udev_properties: source.all_udev_properties(),
+            input_properties,
+            keys,
+            abs_axes,
+            rel_axes,
+            abs_info: source.abs_info(),
+            switches: source.switches(),
+            capabilities,
+            sources,
+            capability_sources,
+            devnode: source.devnode(),
+            sysfs: source.sysfs_path(),
+            size_mm: source.size_mm(),
+            quirks: Arc::default(),
+        }
+    }
+
+    /// Return a new [`EvdevDevice`] for the given evdev device node, e.g.
+    /// `/dev/input/event0` or a symlink to such a node. This opens the
+    /// node and is otherwise identical to [`EvdevDevice::from_fd`].
+    ///
+    /// Returns [`Error::NotAnInputDevice`] if the path is not an evdev node.
+    #[cfg(feature = "udev")]
+    pub fn from_path(path: &Path) -> Result<EvdevDevice, Error> {
+        let cpath = std::fs::canonicalize(path)?;
+        let is_evdev = cpath.starts_with("/dev/input")
+            && cpath
+                .file_name()
+                .map_or(false, |n| n.to_string_lossy().starts_with("event"));
+        if !is_evdev {
+            return Err(Error::NotAnInputDevice(path.to_path_buf()));
+        }
+
+        let f = File::open(cpath)?;
+        EvdevDevice::from_fd(OwnedFd::from(f))
+    }
 
-        // FIXME: can happen if device was removed since
-        let device = device.expect("Unable to find udev devnode");
+    /// Return a new [`EvdevDevice`] based on the sysfs path of an input device,
+    /// e.g. `/sys/class/input/event0`. Unlike [`EvdevDevice::from_fd`] this
+    /// does not require access to the device node, all information is read
+    /// from the sysfs attributes and the udev properties.
+    ///
+    /// Where the path points to the `inputN` device, the `eventN` child device
+    /// is used instead.
+    ///
+    /// Returns [`Error::NotAnInputDevice`] if the path is not an input device
+    /// with an event node.
+    #[cfg(feature = "udev")]
+    pub fn from_syspath(path: &Path) -> Result<EvdevDevice, Error> {
+        let not_input = || Error::NotAnInputDevice(path.to_path_buf());
+        let device = udev::Device::from_syspath(path).map_err(Error::Udev)?;
+        match device.subsystem() {
+            Some(s) if s == "input" => {}
+            _ => return Err(not_input()),
+        };
+
+        let device = if device.devnode().is_some() {
+            device
+        } else {
+            let mut e = udev::Enumerator::new().map_err(Error::Udev)?;
+            e.match_subsystem("input").map_err(Error::Udev)?;
+            e.match_parent(&device).map_err(Error::Udev)?;
+            let mut devices = e.scan_devices().map_err(Error::Udev)?;
+            devices
+                .find(|d| d.sysname().to_string_lossy().starts_with("event"))
+                .ok_or_else(not_input)?
+        };
+
+        // The attributes are on the inputN parent of the eventN device
+        let input = device.parent().ok_or_else(not_input)?;
+        let attr = |name: &str| -> Option<String> {
+            input
+                .attribute_value(name)
+                .map(|v| v.to_string_lossy().trim().to_string())
+        };
+        let id = |name: &str| -> u16 {
+            attr(name)
+                .and_then(|v| u16::from_str_radix(&v, 16).ok())
+                .unwrap_or(0)
+        };
 
         let udev_properties = util::input_id_udev_props(&device);
-        let devnode = device.devnode().map(|n| n.clone().to_owned());
-        let sysfs = device.syspath().to_path_buf();
+        let all_udev_properties = util::all_udev_props(&device);
+        let devnode = device.devnode().map(|n| n.to_owned());
+        let sysfs = Some(device.syspath().to_path_buf());
+        let size_mm = util::udev_prop_u32(&device, "ID_INPUT_WIDTH_MM")
+            .zip(util::udev_prop_u32(&device, "ID_INPUT_HEIGHT_MM"));
 
-        // Map udev to capabilities, then fill in any potentially missing ones
-        let capabilities: Vec<Capability> = udev_properties
-            .iter()
-            .filter(|prop| Capability::from_udev_prop(&prop).is_some())
-            .map(|prop| Capability::from_udev_prop(&prop).unwrap())
+        // The properties and capabilities attributes are hex bitmasks
+        let bitmask = |name: &str| -> Vec<u16> {
+            attr(name)
+                .map(|v| util::parse_bitmask(&v))
+                .unwrap_or_default()
+        };
+        let input_properties: Vec<InputProp> = bitmask("properties")
+            .into_iter()
+            .filter_map(InputProp::from_raw)
             .collect();
-        let capabilities = Capability::extend(capabilities);
+        let keys = bitmask("capabilities/key");
+        let abs_axes = bitmask("capabilities/abs");
+        let rel_axes = bitmask("capabilities/rel");
+        let switches = bitmask("capabilities/sw");
 
-        let fd = OwnedFd::from(f);
-        let device = evdev::Device::from_fd(fd)?;
-        let ids = device.input_id();
+        let name = attr("name").unwrap_or_default();
+        let vid = id("id/vendor");
+        let pid = id("id/product");
+        let version = id("id/version");
+        let driver = util::udev_driver(&device);
+        let (capabilities, sources, capability_sources) = capabilities_from(
+            &udev_properties,
+            &input_properties,
+            &name,
+            (vid, pid, version),
+            driver.as_deref(),
+            &keys,
+            &abs_axes,
+            &rel_axes,
+            GamepadProfile::from_codes(&keys, &abs_axes),
+            &[],
+        );
 
-        let device_index = NEXT_ID.fetch_add(1, Ordering::Relaxed);
         let device = Self {
             node: Node::new(),
-            name: device.name().unwrap().to_string(),
-            vid: ids.vendor(),
-            pid: ids.product(),
+            name,
+            bustype: BusType::from_raw(id("id/bustype")),
+            vid,
+            pid,
+            version,
+            uniq: attr("uniq").filter(|u| !u.is_empty()),
+            phys: attr("phys").filter(|p| !p.is_empty()),
+            driver,
             udev_properties,
+            all_udev_properties,
+            input_properties,
+            keys,
+            abs_axes,
+            rel_axes,
+            // sysfs doesn't export the axis ranges
+            abs_info: HashMap::new(),
+            switches,
             capabilities,
+            sources,
+            capability_sources,
             devnode,
             sysfs,
+            size_mm,
+            quirks: Arc::default(),
         };
 
         Ok(device)
     }
 
+    /// Return a new [`EvdevDevice`] that is not backed by a kernel device,
+    /// with capabilities guessed from the name only.
+    pub(crate) fn from_name(name: &str) -> EvdevDevice {
+        Self::from_parts(name, 0, 0, &[], &[], None)
+    }
+
+    /// Return a new [`EvdevDevice`] from the given parts instead of a kernel
+    /// device. The capabilities are evaluated exactly as for a kernel device,
+    /// including any quirks for the vid/pid. The `udev_properties` are the
+    /// `ID_INPUT_*` udev properties set to 1, e.g. `ID_INPUT_TOUCHPAD`.
+    ///
+    /// This is primarily useful for exercising the classification with
+    /// canned data.
+    pub(crate) fn from_parts(
+        name: &str,
+        vid: u16,
+        pid: u16,
+        udev_properties: &[&str],
+        input_properties: &[InputProp],
+        sysfs: Option<PathBuf>,
+    ) -> EvdevDevice {
+        let udev_properties: Vec<String> = udev_properties.iter().map(|p| p.to_string()).collect();
+        let (capabilities, sources, capability_sources) = capabilities_from(
+            &udev_properties,
+            input_properties,
+            name,
+            (vid, pid, 0),
+            None,
+            &[],
+            &[],
+            &[],
+            None,
+            &[],
+        );
+
+        Self {
+            node: Node::new(),
+            name: name.to_string(),
+            bustype: BusType::Unknown,
+            vid,
+            pid,
+            version: 0,
+            uniq: None,
+            phys: None,
+            driver: None,
+            udev_properties,
+            all_udev_properties: HashMap::new(),
+            input_properties: input_properties.to_vec(),
+            keys: Vec::new(),
+            abs_axes: Vec::new(),
+            rel_axes: Vec::new(),
+            abs_info: HashMap::new(),
+            switches: Vec::new(),
+            capabilities,
+            sources,
+            capability_sources,
+            devnode: None,
+            sysfs,
+            size_mm: None,
+            quirks: Arc::default(),
+        }
+    }
+
+    /// Override the device's name and re-evaluate the capabilities
+    /// guessed from the name.
+    pub(crate) fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
+        self.reevaluate();
+    }
+
+    /// Override the device's bus type and vendor/product id and
+    /// re-evaluate the capabilities, including any quirks for the new ids.
+    pub(crate) fn set_id(&mut self, bustype: BusType, vid: u16, pid: u16) {
+        self.bustype = bustype;
+        self.vid = vid;
+        self.pid = pid;
+        self.reevaluate();
+    }
+
+    fn reevaluate(&mut self) {
+        let (capabilities, sources, capability_sources) = capabilities_from(
+            &self.udev_properties,
+            &self.input_properties,
+            &self.name,
+            (self.vid, self.pid, self.version),
+            self.driver.as_deref(),
+            &self.keys,
+            &self.abs_axes,
+            &self.rel_axes,
+            self.gamepad_profile(),
+            &self.quirks,
+        );
+        self.capabilities = capabilities;
+        self.sources = sources;
+        self.capability_sources = capability_sources;
+    }
+
+    /// Serialize this device into a versioned JSON format, see
+    /// [`EvdevDevice::deserialize`]. The serialized device does not include its
+    /// attachment to a [`DeviceTree`].
+    pub fn serialize(&self) -> String {
+        serialize::to_string(self)
+    }
+
+    /// Reconstruct a device from the data returned by [`EvdevDevice::serialize`].
+    /// Data serialized with a different [`FORMAT_VERSION`] is rejected.
+    ///
+    /// The returned device is not attached to any [`DeviceTree`].
+    pub fn deserialize(data: &str) -> Result<EvdevDevice, Box<dyn StdError>> {
+        serialize::from_str(data)
+    }
+
     /// Return the device's name as advertised by the kernel. For many
     /// HID devices, this name will have a HID-application specific
     /// suffix like "Pen", "Mouse", "Consumer Control".
+    ///
+    /// The name is empty if the device does not have a name. Any bytes in
+    /// the name that are not valid UTF-8 are replaced.
     pub fn name(&'a self) -> &'a str {
         &self.name
     }
@@ -100,6 +437,124 @@ impl<'a> EvdevDevice {
         &self.udev_properties
     }
 
+    /// Return the capabilities as tagged by udev, i.e. the
+    /// [`EvdevDevice::udev_types`] mapped to capabilities. Unlike
+    /// [`HasCapability::capabilities`] this is not affected by quirks or
+    /// anything else whodat derives.
+    pub fn udev_capabilities(&self) -> Vec<Capability> {
+        let mut caps: Vec<Capability> = self
+            .udev_properties
+            .iter()
+            .flat_map(|prop| Capability::from_udev_prop(prop).iter().copied())
+            .collect();
+        caps.sort();
+        caps.dedup();
+        caps
+    }
+
+    /// Return the value of the given udev property, e.g. `ID_MODEL` or
+    /// `ID_PATH`, if set on this device. Unlike [`EvdevDevice::udev_types`]
+    /// this is not restricted to the `ID_INPUT_*` properties.
+    pub fn udev_property(&self, name: &str) -> Option<String> {
+        self.all_udev_properties.get(name).cloned()
+    }
+
+    /// Return all udev properties of this device and their values.
+    pub fn udev_properties_all(&self) -> HashMap<String, String> {
+        self.all_udev_properties.clone()
+    }
+
+    /// Return the kernel's `INPUT_PROP_*` properties set on this device.
+    /// If the result is an empty vector, no properties are set or the
+    /// device does not support querying them.
+    pub fn input_properties(&self) -> Vec<InputProp> {
+        self.input_properties.clone()
+    }
+
+    /// Return the `EV_KEY` codes supported by this device. This is the raw
+    /// kernel view of the device, it is not affected by any quirks.
+    #[cfg(feature = "udev")]
+    pub fn supported_keys(&self) -> Vec<evdev::Key> {
+        self.keys.iter().map(|c| evdev::Key::new(*c)).collect()
+    }
+
+    /// Return the `EV_ABS` codes supported by this device. This is the raw
+    /// kernel view of the device, it is not affected by any quirks.
+    #[cfg(feature = "udev")]
+    pub fn supported_abs(&self) -> Vec<evdev::AbsoluteAxisType> {
+        self.abs_axes.iter().map(|c| evdev::AbsoluteAxisType(*c)).collect()
+    }
+
+    /// Return the range and resolution of the given absolute axis as reported
+    /// by the kernel. Returns `None` if the device does not have this axis or
+    /// if the axis information is not available, e.g. for a device created
+    /// with [`EvdevDevice::from_syspath`].
+    ///
+    /// A resolution of zero means the kernel does not know the resolution.
+    #[cfg(feature = "udev")]
+    pub fn abs_resolution(&self, axis: evdev::AbsoluteAxisType) -> Option<AbsResolution> {
+        self.abs_info.get(&axis.0).copied()
+    }
+
+    /// Return the `EV_REL` codes supported by this device. This is the raw
+    /// kernel view of the device, it is not affected by any quirks.
+    #[cfg(feature = "udev")]
+    pub fn supported_rel(&self) -> Vec<evdev::RelativeAxisType> {
+        self.rel_axes.iter().map(|c| evdev::RelativeAxisType(*c)).collect()
+    }
+
+    /// Return the switches of this device, e.g. [`SwitchType::Lid`] for a
+    /// laptop lid switch. A device without any `EV_SW` codes returns an
+    /// empty vector, as do switches this crate does not know about.
+    pub fn switches(&self) -> Vec<SwitchType> {
+        self.switches
+            .iter()
+            .filter_map(|c| SwitchType::from_raw(*c))
+            .collect()
+    }
+
+    /// Returns the button and axis inventory of this device if it is a
+    /// gaming device, i.e. if it has any joystick or gamepad buttons
+    /// (`BTN_JOYSTICK` or `BTN_GAMEPAD` ranges) or racing wheel pedals.
+    /// Returns `None` for all other devices.
+    pub fn gamepad_profile(&self) -> Option<GamepadProfile> {
+        GamepadProfile::from_codes(&self.keys, &self.abs_axes)
+    }
+
+    /// Returns the number of buttons, rings and strips of this device if it
+    /// is a [`Capability::TabletPad`]. Returns `None` for all other devices.
+    pub fn tablet_pad_layout(&self) -> Option<TabletPadLayout> {
+        if !self.capabilities.contains(&Capability::TabletPad) {
+            return None;
+        }
+        Some(TabletPadLayout::from_codes(&self.keys, &self.abs_axes))
+    }
+
+    /// Returns true if the device supports high-resolution scrolling, i.e. it
+    /// has `REL_WHEEL_HI_RES` or `REL_HWHEEL_HI_RES`. A device with only the
+    /// legacy `REL_WHEEL`/`REL_HWHEEL` axes does not have high-resolution scrolling.
+    #[cfg(feature = "udev")]
+    pub fn has_high_res_scroll(&self) -> bool {
+        use evdev::RelativeAxisType;
+
+        self.rel_axes.iter().any(|c| {
+            *c == RelativeAxisType::REL_WHEEL_HI_RES.0 || *c == RelativeAxisType::REL_HWHEEL_HI_RES.0
+        })
+    }
+
+    /// Returns true if the device scrolls but does not move, i.e. it has
+    /// `REL_WHEEL` or `REL_HWHEEL` but neither `REL_X` nor `REL_Y`, e.g.
+    /// the scroll wheel node of a Contour RollerMouse. Such a device is not
+    /// a [`Capability::Pointer`], see [`Capability::Dial`].
+    pub fn is_scroll_only(&self) -> bool {
+        is_scroll_only(&self.rel_axes)
+    }
+
+    /// The bus this device is connected through
+    pub fn bustype(&self) -> BusType {
+        self.bustype
+    }
+
     /// The 16-bit Vendor ID
     pub fn vid(&self) -> u16 {
         self.vid
@@ -110,21 +565,1181 @@ impl<'a> EvdevDevice {
         self.pid
     }
 
+    /// Return the product version of this device. Devices typically use
+    /// this for the firmware revision, so the same vid/pid may behave
+    /// differently depending on the version.
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// The unique identifier of this device as reported by the kernel
+    /// (`EVIOCGUNIQ`), e.g. a serial number or the Bluetooth address.
+    /// Most devices do not have a unique identifier, in which case this
+    /// function returns `None`.
+    pub fn uniq(&self) -> Option<String> {
+        self.uniq.clone()
+    }
+
+    /// The physical topology path of this device as reported by the kernel
+    /// (`EVIOCGPHYS`), e.g. `usb-0000:00:14.0-2/input0`. The format of this
+    /// path depends on the driver. Returns `None` if the device does not
+    /// have a physical path.
+    pub fn phys(&self) -> Option<String> {
+        self.phys.clone()
+    }
+
+    /// The name of the kernel driver bound to this device, e.g.
+    /// `hid-generic` or a dedicated driver like `hid-sony` or `wacom`.
+    /// This is the driver of the closest parent device with a driver, an
+    /// input device itself never has one.
+    ///
+    /// Some drivers are definitive about the device type, e.g. a device
+    /// driven by `wacom` is a tablet whatever its event bits say, see
+    /// [`HasCapability::capabilities`]. Returns `None` if the driver is
+    /// unknown, e.g. for a device created without sysfs.
+    pub fn driver(&self) -> Option<String> {
+        self.driver.clone()
+    }
+
+    /// Replace the device's udev properties with the given name/value pairs
+    /// and re-evaluate the capabilities as for a device with these properties.
+    pub(crate) fn set_udev_properties(&mut self, props: &[(String, String)]) {
+        self.udev_properties =
+            util::input_id_props(props.iter().map(|(n, v)| (n.as_str(), v.as_str())));
+        self.all_udev_properties = props.iter().cloned().collect();
+        let prop = |name: &str| {
+            self.all_udev_properties
+                .get(name)
+                .and_then(|v| v.parse::<u32>().ok())
+        };
+        if let (Some(w), Some(h)) = (prop("ID_INPUT_WIDTH_MM"), prop("ID_INPUT_HEIGHT_MM")) {
+            self.size_mm = Some((w, h));
+        }
+        self.reevaluate();
+    }
+
+    pub(crate) fn set_uniq(&mut self, uniq: &str) {
+        self.uniq = Some(uniq.to_string());
+    }
+
+    pub(crate) fn set_phys(&mut self, phys: &str) {
+        self.phys = Some(phys.to_string());
+    }
+
+    /// Use the given quirks in addition to the compiled-in ones and
+    /// re-evaluate the capabilities, see [`DeviceTree::with_quirks`].
+    pub(crate) fn set_quirks(&mut self, quirks: Arc<Vec<Quirk>>) {
+        if quirks.is_empty() && self.quirks.is_empty() {
+            return;
+        }
+        self.quirks = quirks;
+        self.reevaluate();
+    }
+
+    /// The quirk applied to this device, if any
+    pub(crate) fn quirk(&self) -> Option<Quirk> {
+        quirks::quirk_for(&self.quirks, self.vid, self.pid, Some(self.version))
+    }
+
+    /// The physical size of the device's sensor area as `(width, height)` in
+    /// millimeters. This is taken from the udev `ID_INPUT_WIDTH_MM` and
+    /// `ID_INPUT_HEIGHT_MM` properties or, where those are missing, calculated
+    /// from the `ABS_X`/`ABS_Y` axis ranges and resolution.
+    ///
+    /// Returns `None` if the size is unknown, e.g. for relative devices.
+    pub fn size_mm(&self) -> Option<(u32, u32)> {
+        self.size_mm
+    }
+
     pub fn devnode(&self) -> &Option<PathBuf> {
         &self.devnode
     }
 
-    pub fn sysfs_path(&self) -> &PathBuf {
+    /// The sysfs path of this device or `None` where the device was not
+    /// created from a kernel device, e.g. with [`Builder::name`].
+    pub fn sysfs_path(&self) -> &Option<PathBuf> {
         &self.sysfs
     }
 
-    pub(crate) fn set_parent(&mut self, parent: &PhysicalDevice) {
+    /// The device nodes of the other evdev devices of the same physical
+    /// device, e.g. the touchpad node of a gamepad. Attaching these to a
+    /// [`DeviceTree`] together with this device gives the complete
+    /// [`PhysicalDevice`], even where this device alone does not reveal
+    /// the physical device type.
+    ///
+    /// Returns an empty vector if the device has no siblings or was not
+    /// created from a kernel device.
+    #[cfg(feature = "udev")]
+    pub fn sibling_nodes(&self) -> Vec<PathBuf> {
+        let device = match self.sysfs.as_ref().map(udev::Device::from_syspath) {
+            Some(Ok(device)) => device,
+            _ => return Vec::new(),
+        };
+        // Devices on other interfaces of the same USB device are siblings
+        // too, see PhysicalDevice::match_device
+        let root = match physical_device::evdev_anchor(&device) {
+            Some(anchor) => physical_device::usb_device(&anchor).unwrap_or(anchor),
+            None => return Vec::new(),
+        };
+
+        let mut e = match udev::Enumerator::new() {
+            Ok(e) => e,
+            Err(_) => return Vec::new(),
+        };
+        if e.match_subsystem("input").is_err() {
+            return Vec::new();
+        }
+        let devices = match e.scan_devices() {
+            Ok(devices) => devices,
+            Err(_) => return Vec::new(),
+        };
+        let mut nodes: Vec<PathBuf> = devices
+            .filter(|d| {
+                d.sysname()
+                    .to_str()
+                    .map_or(false, |n| n.starts_with("event"))
+            })
+            .filter(|d| d.syspath().starts_with(root.syspath()))
+            .filter(|d| Some(d.syspath()) != self.sysfs.as_deref())
+            .filter_map(|d| d.devnode().map(|n| n.to_owned()))
+            .collect();
+        nodes.sort();
+        nodes
+    }
+
+    /// Returns true if this is a virtual device, e.g. one created via
+    /// `uinput`, see [`DeviceTree::skip_virtual`]. A device not created from
+    /// a kernel device is not virtual.
+    pub fn is_virtual(&self) -> bool {
+        self.sysfs.as_deref().map_or(false, util::is_virtual)
+    }
+
+    /// Returns how confident we are in the classification of this device,
+    /// in the range `[0.0, 1.0]`, see [`PhysicalDevice::confidence`].
+    /// A device classified only from its name, e.g. one created with the
+    /// [`Builder`], has a low confidence.
+    pub fn confidence(&self) -> f32 {
+        self.sources
+            .iter()
+            .map(|s| s.confidence())
+            .fold(0.0, f32::max)
+    }
+
+    /// The sources that contributed to this device's capabilities
+    pub(crate) fn sources(&self) -> &Vec<Source> {
+        &self.sources
+    }
+
+    /// The source of each of this device's capabilities
+    pub(crate) fn capability_sources(&self) -> &[(Capability, Source)] {
+        &self.capability_sources
+    }
+
+    pub(crate) fn set_parent(&mut self, parent: DeviceIndex) {
         //if let Some(ref mut node) = self.node {
-        self.node.set_parent(parent.index());
+        self.node.set_parent(parent);
         //}
     }
 
+    pub(crate) fn set_node(&mut self, node: Node) {
+        self.node = node;
+    }
+
     pub(crate) fn index(&self) -> DeviceIndex {
         self.node.idx.clone()
     }
 }
+
+const BTN_TOOL_PEN: u16 = 0x140;
+const BTN_STYLUS: u16 = 0x14b;
+const REL_X: u16 = 0x00;
+const REL_Y: u16 = 0x01;
+const REL_HWHEEL: u16 = 0x06;
+const REL_DIAL: u16 = 0x07;
+const REL_WHEEL: u16 = 0x08;
+const BTN_MISC: u16 = 0x100;
+const BTN_MOUSE: u16 = 0x110;
+const BTN_JOYSTICK: u16 = 0x120;
+const BTN_GAMEPAD: u16 = 0x130;
+const BTN_DIGI: u16 = 0x140;
+const BTN_TOOL_FINGER: u16 = 0x145;
+const BTN_TOUCH: u16 = 0x14a;
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+const ABS_MT_POSITION_X: u16 = 0x35;
+const ABS_MT_POSITION_Y: u16 = 0x36;
+
+/// Derive the capabilities from the kernel's event bits alone, following
+/// the rules of udev's `input_id` builtin. This is only used where udev did
+/// not tag the device, see [`capabilities_from`].
+fn capabilities_from_bits(
+    input_properties: &[InputProp],
+    keys: &[u16],
+    abs_axes: &[u16],
+    rel_axes: &[u16],
+) -> Vec<Capability> {
+    let has_key = |code: u16| keys.contains(&code);
+    let has_key_in = |range: std::ops::Range<u16>| keys.iter().any(|k| range.contains(k));
+    let has_abs = |code: u16| abs_axes.contains(&code);
+    let has_rel = |code: u16| rel_axes.contains(&code);
+    let is_direct = input_properties.contains(&InputProp::Direct);
+
+    let mut caps = Vec::new();
+    // Any key below the button range makes it a keyboard for udev, we
+    // do not distinguish full keyboards from a few keys
+    if has_keyboard_keys(keys) {
+        caps.push(Capability::Keyboard);
+    }
+    let has_mouse_buttons = has_key_in(BTN_MOUSE..BTN_MOUSE + 0x10);
+    if has_rel(REL_X) && has_rel(REL_Y) && has_mouse_buttons {
+        caps.push(Capability::Pointer);
+    }
+    if has_key_in(BTN_JOYSTICK..BTN_GAMEPAD) {
+        caps.push(Capability::Joystick);
+    }
+    if has_key(BTN_GAMEPAD) {
+        caps.push(Capability::Joystick);
+        caps.push(Capability::Gamepad);
+    }
+
+    let has_xy = has_abs(ABS_X) && has_abs(ABS_Y);
+    let has_mt = has_abs(ABS_MT_POSITION_X) && has_abs(ABS_MT_POSITION_Y);
+    if has_xy || has_mt {
+        if has_key(BTN_TOOL_PEN) || has_key(BTN_STYLUS) {
+            caps.push(Capability::Tablet);
+        } else if is_direct && (has_key(BTN_TOUCH) || has_mt) {
+            caps.push(Capability::Touchscreen);
+        } else if has_key(BTN_TOOL_FINGER) || (has_mt && has_key(BTN_TOUCH)) {
+            caps.push(Capability::Touchpad);
+        } else if has_mouse_buttons && !has_key_in(BTN_DIGI..BTN_DIGI + 0x10) {
+            // e.g. the absolute pointer of a virtual machine
+            caps.push(Capability::Pointer);
+        }
+    }
+
+    caps.sort();
+    caps.dedup();
+    caps
+}
+
+/// `KEY_KP0` to `KEY_KP9`
+const KEYPAD_DIGITS: &[u16] = &[82, 79, 80, 81, 75, 76, 77, 71, 72, 73];
+/// The letter rows `KEY_Q..KEY_P`, `KEY_A..KEY_L` and `KEY_Z..KEY_M`
+const ALPHA_KEYS: &[std::ops::RangeInclusive<u16>] = &[16..=25, 30..=38, 44..=50];
+
+/// Returns true if the keys are those of a numeric keypad, i.e. all keypad
+/// digits but none of the letters. Many numpads also send some regular keys,
+/// e.g. `KEY_BACKSPACE` or `KEY_TAB`, so only the letters count.
+fn is_numpad(keys: &[u16]) -> bool {
+    KEYPAD_DIGITS.iter().all(|k| keys.contains(k))
+        && !keys
+            .iter()
+            .any(|k| ALPHA_KEYS.iter().any(|range| range.contains(k)))
+}
+
+/// Returns true if any of the keys is a keyboard key, i.e. below the button
+/// range. `KEY_RESERVED` is not a key.
+fn has_keyboard_keys(keys: &[u16]) -> bool {
+    keys.iter().any(|k| *k > 0 && *k < BTN_MISC)
+}
+
+/// Returns true if the relative axes scroll but do not move
+fn is_scroll_only(rel_axes: &[u16]) -> bool {
+    let has_rel = |code: u16| rel_axes.contains(&code);
+    (has_rel(REL_WHEEL) || has_rel(REL_HWHEEL)) && !has_rel(REL_X) && !has_rel(REL_Y)
+}
+
+/// Map the udev properties and kernel properties to capabilities, then fill in
+/// any potentially missing ones. Returns the capabilities, the sources
+/// that contributed to them and the source of each capability.
+///
+/// Where udev did not tag the device the capabilities are derived from the
+/// evdev bits instead, the same way udev would have.
+///
+/// Some kernel drivers only handle one type of device, the driver overrides
+/// what udev or the evdev bits say for those.
+///
+/// The name is only used where neither udev nor the kernel gave us any
+/// capabilities, names are too unreliable to second-guess either.
+/// Any quirk for the vid/pid is applied last, a quirk in `external` takes
+/// precedence over a compiled-in one.
+#[allow(clippy::too_many_arguments)]
+fn capabilities_from(
+    udev_properties: &[String],
+    input_properties: &[InputProp],
+    name: &str,
+    (vid, pid, version): (u16, u16, u16),
+    driver: Option<&str>,
+    keys: &[u16],
+    abs_axes: &[u16],
+    rel_axes: &[u16],
+    profile: Option<GamepadProfile>,
+    external: &[Quirk],
+) -> (Vec<Capability>, Vec<Source>, Vec<(Capability, Source)>) {
+    let mut sources = Vec::new();
+    // The source of each capability as it was added
+    let mut provenance: Vec<(Capability, Source)> = Vec::new();
+    let mut capabilities: Vec<Capability> = udev_properties
+        .iter()
+        .flat_map(|prop| Capability::from_udev_prop(prop).iter().copied())
+        .collect();
+    if !capabilities.is_empty() {
+        sources.push(Source::Udev);
+        provenance.extend(capabilities.iter().map(|c| (*c, Source::Udev)));
+        log::trace!("{name}: {capabilities:?} from udev {udev_properties:?}");
+    }
+    // Without udev tags, e.g. for a device from /proc or a capture without
+    // udev data, the kernel's bits tell us what udev would have said
+    if capabilities.is_empty() {
+        capabilities = capabilities_from_bits(input_properties, keys, abs_axes, rel_axes);
+        if !capabilities.is_empty() {
+            log::trace!("{name}: {capabilities:?} from the evdev bits");
+            sources.push(Source::Kernel);
+            provenance.extend(capabilities.iter().map(|c| (*c, Source::Kernel)));
+        }
+    }
+    // udev tags most trackballs and some pointing sticks as mice, trust the
+    // name where it's explicit. Others need a quirk.
+    if capabilities.contains(&Capability::Pointer) {
+        for cap in [Capability::Trackball, Capability::Pointingstick] {
+            if !capabilities.contains(&cap) && Capability::from_name(name).contains(&cap) {
+                log::trace!("{name}: {cap:?} from the name of a pointer");
+                capabilities.push(cap);
+                sources.push(Source::Name);
+                provenance.push((cap, Source::Name));
+            }
+        }
+    }
+    // udev doesn't have a tag for clickpads, the kernel does. The kernel
+    // cannot tell a pressurepad from a clickpad, those are quirked.
+    if input_properties.contains(&InputProp::Buttonpad) {
+        log::trace!("{name}: Clickpad from INPUT_PROP_BUTTONPAD");
+        capabilities.push(Capability::Clickpad);
+        sources.push(Source::Kernel);
+        provenance.push((Capability::Clickpad, Source::Kernel));
+    }
+    if input_properties.contains(&InputProp::Accelerometer)
+        && !capabilities.contains(&Capability::Accelerometer)
+    {
+        log::trace!("{name}: Accelerometer from INPUT_PROP_ACCELEROMETER");
+        capabilities.push(Capability::Accelerometer);
+        sources.push(Source::Kernel);
+        provenance.push((Capability::Accelerometer, Source::Kernel));
+    }
+    // The kernel marks most pointing sticks, udev doesn't always catch those
+    if input_properties.contains(&InputProp::PointingStick)
+        && !capabilities.contains(&Capability::Pointingstick)
+    {
+        log::trace!("{name}: Pointingstick from INPUT_PROP_POINTING_STICK");
+        capabilities.push(Capability::Pointingstick);
+        sources.push(Source::Kernel);
+        provenance.push((Capability::Pointingstick, Source::Kernel));
+    }
+    // udev tags numpads as keyboards, only the missing letters tell them apart
+    if capabilities.contains(&Capability::Keyboard) && is_numpad(keys) {
+        log::trace!("{name}: Numpad from the keypad keys without KEY_A..KEY_Z");
+        capabilities.push(Capability::Numpad);
+        sources.push(Source::Kernel);
+        provenance.push((Capability::Numpad, Source::Kernel));
+    }
+    // udev tags pens as tablets, even where the pen is on a touchscreen
+    if keys.contains(&BTN_TOOL_PEN) || keys.contains(&BTN_STYLUS) {
+        log::trace!("{name}: Stylus from BTN_TOOL_PEN/BTN_STYLUS");
+        capabilities.push(Capability::Stylus);
+        sources.push(Source::Kernel);
+        provenance.push((Capability::Stylus, Source::Kernel));
+    }
+    // udev doesn't know dials. A dial or wheel without x/y axes is a knob,
+    // not a mouse, even where something tagged it as one. A keyboard's
+    // Consumer Control node may have a wheel too, that's still a keyboard.
+    let has_rel = |code: u16| rel_axes.contains(&code);
+    let is_dial = has_rel(REL_DIAL) && !has_rel(REL_X) && !has_rel(REL_Y);
+    let is_mouse_only = udev_properties.iter().any(|p| p == "ID_INPUT_MOUSE")
+        && udev_properties
+            .iter()
+            .all(|p| p == "ID_INPUT_MOUSE" || Capability::from_udev_prop(p).is_empty());
+    let is_scroll_wheel = is_scroll_only(rel_axes) && (!has_keyboard_keys(keys) || is_mouse_only);
+    if is_dial || is_scroll_wheel {
+        log::trace!("{name}: Dial from REL_DIAL/REL_WHEEL/REL_HWHEEL without REL_X/REL_Y");
+        capabilities.retain(|c| *c != Capability::Pointer);
+        capabilities.push(Capability::Dial);
+        sources.push(Source::Kernel);
+        provenance.push((Capability::Dial, Source::Kernel));
+    }
+    // udev only knows joysticks, the axes tell us what kind
+    if let Some(profile) = profile {
+        if profile.is_wheel_like() {
+            log::trace!("{name}: RacingWheel from the axes {profile:?}");
+            capabilities.retain(|c| *c != Capability::Joystick);
+            capabilities.push(Capability::RacingWheel);
+            sources.push(Source::Kernel);
+            provenance.push((Capability::RacingWheel, Source::Kernel));
+        } else if profile.is_gamepad_like() && !capabilities.contains(&Capability::Gamepad) {
+            log::trace!("{name}: Gamepad from the axes {profile:?}");
+            capabilities.push(Capability::Gamepad);
+            sources.push(Source::Kernel);
+            provenance.push((Capability::Gamepad, Source::Kernel));
+        }
+    }
+    // Everything the wacom driver handles is part of a tablet, including
+    // nodes that udev or the bits make out to be a mouse, e.g. a puck
+    let is_tablet_part = |c: &Capability| {
+        matches!(
+            c,
+            Capability::Tablet
+                | Capability::TabletPad
+                | Capability::Touchpad
+                | Capability::Touchscreen
+        )
+    };
+    if driver == Some("wacom") && !capabilities.iter().any(is_tablet_part) {
+        log::trace!("{name}: Tablet from the wacom driver");
+        capabilities.retain(|c| *c != Capability::Pointer);
+        capabilities.push(Capability::Tablet);
+        sources.push(Source::Kernel);
+        provenance.push((Capability::Tablet, Source::Kernel));
+    }
+    if capabilities.is_empty() {
+        capabilities = Capability::from_name(name);
+        if !capabilities.is_empty() {
+            log::trace!("{name}: {capabilities:?} from the name only");
+            sources.push(Source::Name);
+            provenance.extend(capabilities.iter().map(|c| (*c, Source::Name)));
+        }
+    }
+    let quirk = quirks::quirk_for(external, vid, pid, Some(version));
+    let capabilities = match &quirk {
+        Some(quirk) => {
+            log::debug!("{name}: applying quirk for {vid:04x}:{pid:04x} {quirk:?}");
+            sources.push(Source::Quirk);
+            quirk.apply(capabilities)
+        }
+        None => Capability::extend(capabilities),
+    };
+
+    // A capability added more than once counts with its most reliable
+    // source. A capability implied by others (see Capability::extend) is
+    // only as reliable as our least reliable source.
+    let added_by_quirk =
+        |cap: Capability| quirk.as_ref().map_or(false, |q| q.added().contains(&cap));
+    let capability_sources = capabilities
+        .iter()
+        .map(|cap| {
+            let source = provenance
+                .iter()
+                .filter(|(c, _)| c == cap)
+                .map(|(_, s)| *s)
+                .max()
+                .or_else(|| added_by_quirk(*cap).then_some(Source::Quirk))
+                .or_else(|| sources.iter().copied().min())
+                .unwrap_or(Source::Name);
+            (*cap, source)
+        })
+        .collect();
+
+    (capabilities, sources, capability_sources)
+}
+
+/// The [`EvdevSource`] backed by a kernel device and its udev device.
+#[cfg(feature = "udev")]
+pub(crate) struct KernelEvdev {
+    device: evdev::Device,
+    udev_properties: Vec<String>,
+    all_udev_properties: HashMap<String, String>,
+    udev_size_mm: Option<(u32, u32)>,
+    driver: Option<String>,
+    devnode: Option<PathBuf>,
+    sysfs: PathBuf,
+}
+
+#[cfg(feature = "udev")]
+impl KernelEvdev {
+    pub(crate) fn from_fd(fd: OwnedFd) -> Result<KernelEvdev, Error> {
+        // Get st_rdev from the fd so we can later look this up with udev
+        let f = File::from(fd);
+        let meta = f.metadata()?;
+        let rdev = meta.st_rdev();
+
+        // Now fetch out the udev properties. The device may have been
+        // removed since the fd was opened
+        let device = util::udev_device_by_devnum("input", "event*", rdev)?;
+
+        Self::from_udev(f, &device)
+    }
+
+    /// Return a new [`KernelEvdev`] for the opened device node and its
+    /// udev device, e.g. where the caller already scanned udev.
+    pub(crate) fn from_udev(
+        f: File,
+        device: &udev::Device,
+    ) -> Result<KernelEvdev, Error> {
+        let udev_properties = util::input_id_udev_props(device);
+        let all_udev_properties = util::all_udev_props(device);
+        let devnode = device.devnode().map(|n| n.to_owned());
+        let sysfs = device.syspath().to_path_buf();
+        let udev_size_mm = util::udev_prop_u32(device, "ID_INPUT_WIDTH_MM")
+            .zip(util::udev_prop_u32(device, "ID_INPUT_HEIGHT_MM"));
+        let driver = util::udev_driver(device);
+
+        let device = evdev::Device::from_fd(OwnedFd::from(f))?;
+
+        Ok(KernelEvdev {
+            device,
+            udev_properties,
+            all_udev_properties,
+            udev_size_mm,
+            driver,
+            devnode,
+            sysfs,
+        })
+    }
+}
+
+#[cfg(feature = "udev")]
+impl EvdevSource for KernelEvdev {
+    fn name(&self) -> String {
+        // Some devices have a blank name, that's not a reason to fail
+        self.device.name().unwrap_or_default().to_string()
+    }
+
+    fn input_id(&self) -> (u16, u16, u16) {
+        let ids = self.device.input_id();
+        (ids.bus_type().0, ids.vendor(), ids.product())
+    }
+
+    fn version(&self) -> u16 {
+        self.device.input_id().version()
+    }
+
+    fn phys(&self) -> Option<String> {
+        self.device
+            .physical_path()
+            .filter(|p| !p.is_empty())
+            .map(|p| p.to_string())
+    }
+
+    fn driver(&self) -> Option<String> {
+        self.driver.clone()
+    }
+
+    fn uniq(&self) -> Option<String> {
+        self.device
+            .unique_name()
+            .filter(|u| !u.is_empty())
+            .map(|u| u.to_string())
+    }
+
+    fn udev_properties(&self) -> Vec<String> {
+        self.udev_properties.clone()
+    }
+
+    fn all_udev_properties(&self) -> HashMap<String, String> {
+        self.all_udev_properties.clone()
+    }
+
+    fn input_properties(&self) -> Vec<u16> {
+        self.device.properties().iter().map(|p| p.0).collect()
+    }
+
+    fn keys(&self) -> Vec<u16> {
+        self.device
+            .supported_keys()
+            .map(|keys| keys.iter().map(|k| k.code()).collect())
+            .unwrap_or_default()
+    }
+
+    fn abs_axes(&self) -> Vec<u16> {
+        self.device
+            .supported_absolute_axes()
+            .map(|axes| axes.iter().map(|a| a.0).collect())
+            .unwrap_or_default()
+    }
+
+    fn rel_axes(&self) -> Vec<u16> {
+        self.device
+            .supported_relative_axes()
+            .map(|axes| axes.iter().map(|a| a.0).collect())
+            .unwrap_or_default()
+    }
+
+    fn abs_info(&self) -> HashMap<u16, AbsResolution> {
+        let axes = match self.device.supported_absolute_axes() {
+            Some(axes) => axes,
+            None => return HashMap::new(),
+        };
+        let absinfo = match self.device.get_abs_state() {
+            Ok(absinfo) => absinfo,
+            Err(_) => return HashMap::new(),
+        };
+        axes.iter()
+            .map(|axis| {
+                let info = absinfo[axis.0 as usize];
+                let resolution = AbsResolution {
+                    minimum: info.minimum,
+                    maximum: info.maximum,
+                    resolution: info.resolution,
+                    fuzz: info.fuzz,
+                    flat: info.flat,
+                };
+                (axis.0, resolution)
+            })
+            .collect()
+    }
+
+    fn switches(&self) -> Vec<u16> {
+        self.device
+            .supported_switches()
+            .map(|switches| switches.iter().map(|s| s.0).collect())
+            .unwrap_or_default()
+    }
+
+    fn size_mm(&self) -> Option<(u32, u32)> {
+        self.udev_size_mm.or_else(|| size_from_absinfo(&self.device))
+    }
+
+    fn devnode(&self) -> Option<PathBuf> {
+        self.devnode.clone()
+    }
+
+    fn sysfs_path(&self) -> Option<PathBuf> {
+        Some(self.sysfs.clone())
+    }
+}
+
+/// Calculate the physical size in mm from the `ABS_X`/`ABS_Y` axis ranges,
+/// if the device has those axes and the kernel provides a resolution.
+#[cfg(feature = "udev")]
+fn size_from_absinfo(device: &evdev::Device) -> Option<(u32, u32)> {
+    use evdev::AbsoluteAxisType;
+
+    let axes = device.supported_absolute_axes()?;
+    if !axes.contains(AbsoluteAxisType::ABS_X) || !axes.contains(AbsoluteAxisType::ABS_Y) {
+        return None;
+    }
+
+    let absinfo = device.get_abs_state().ok()?;
+    let size = |axis: AbsoluteAxisType| {
+        let info = absinfo[axis.0 as usize];
+        if info.resolution <= 0 || info.maximum <= info.minimum {
+            return None;
+        }
+        Some(((info.maximum - info.minimum) / info.resolution) as u32)
+    };
+
+    Some((size(AbsoluteAxisType::ABS_X)?, size(AbsoluteAxisType::ABS_Y)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_MUTE: u16 = 113;
+    const KEY_VOLUMEDOWN: u16 = 114;
+    const KEY_VOLUMEUP: u16 = 115;
+
+    fn with_bits(mut device: EvdevDevice, keys: &[u16], rel_axes: &[u16]) -> EvdevDevice {
+        device.keys = keys.to_vec();
+        device.rel_axes = rel_axes.to_vec();
+        device.reevaluate();
+        device
+    }
+
+    #[test]
+    fn test_scroll_only_is_dial() {
+        let device = EvdevDevice::from_parts(
+            "Contour Design RollerMouse Wheel",
+            0x0b33,
+            0x1000,
+            &["ID_INPUT_MOUSE"],
+            &[],
+            None,
+        );
+        let device = with_bits(device, &[BTN_MOUSE], &[REL_WHEEL, REL_HWHEEL]);
+        assert!(device.is_scroll_only());
+        assert!(device.has(Capability::Dial));
+        assert!(!device.has(Capability::Pointer));
+
+        // udev only calls it a mouse, so the keys don't make it a keyboard
+        let device = with_bits(device, &[KEY_VOLUMEUP], &[REL_WHEEL]);
+        assert!(device.has(Capability::Dial));
+    }
+
+    #[test]
+    fn test_consumer_control_wheel_is_not_dial() {
+        let phys = "usb-0000:00:14.0-3/input1";
+        let mut keyboard = EvdevDevice::from_parts(
+            "Logitech K800 Keyboard",
+            0x046d,
+            0xc52b,
+            &["ID_INPUT_KEY", "ID_INPUT_KEYBOARD"],
+            &[],
+            None,
+        );
+        keyboard.set_phys(phys);
+        let consumer_control = EvdevDevice::from_parts(
+            "Logitech K800 Consumer Control",
+            0x046d,
+            0xc52b,
+            &["ID_INPUT_KEY", "ID_INPUT_MOUSE"],
+            &[],
+            None,
+        );
+        let mut consumer_control = with_bits(
+            consumer_control,
+            &[KEY_MUTE, KEY_VOLUMEDOWN, KEY_VOLUMEUP],
+            &[REL_HWHEEL],
+        );
+        consumer_control.set_phys(phys);
+        assert!(consumer_control.is_scroll_only());
+        assert!(!consumer_control.has(Capability::Dial));
+        assert!(consumer_control.has(Capability::Keyboard));
+
+        let mut tree = DeviceTree::new();
+        let kidx = tree.attach_evdev_device(keyboard);
+        let cidx = tree.attach_evdev_device(consumer_control);
+        let pidx = tree.get_evdev_device(&kidx).unwrap().parent();
+        assert_eq!(tree.get_evdev_device(&cidx).unwrap().parent(), pidx);
+        let parent = tree.get_parent_device(&pidx).unwrap();
+        assert!(matches!(
+            parent.abstract_types()[..],
+            [AbstractType::Keyboard]
+        ));
+    }
+
+    #[test]
+    fn test_from_parts() {
+        use Capability::*;
+
+        let touchpad = EvdevDevice::from_parts(
+            "Sony Interactive Entertainment DualSense Wireless Controller Touchpad",
+            0x054c,
+            0x0ce6,
+            &["ID_INPUT_TOUCHPAD"],
+            &[InputProp::Buttonpad],
+            None,
+        );
+        // The DualSense quirk adds the gamepad
+        assert_eq!(
+            touchpad.capabilities(),
+            vec![Pointer, Touchpad, Clickpad, Gamepad]
+        );
+
+        let mouse = EvdevDevice::from_parts(
+            "Logitech USB Optical Mouse",
+            0x046d,
+            0xc077,
+            &["ID_INPUT_MOUSE"],
+            &[],
+            None,
+        );
+        assert_eq!(mouse.capabilities(), vec![Pointer]);
+        assert_eq!(mouse.sources(), &vec![Source::Udev]);
+
+        let clickpad = EvdevDevice::from_parts(
+            "SYNA8004:00 06CB:CD8B Touchpad",
+            0x06cb,
+            0xcd8b,
+            &["ID_INPUT_TOUCHPAD"],
+            &[InputProp::Buttonpad],
+            None,
+        );
+        assert_eq!(clickpad.capabilities(), vec![Pointer, Touchpad, Clickpad]);
+
+        let pen = EvdevDevice::from_parts(
+            "Wacom Intuos Pro M Pen",
+            0x056a,
+            0x0357,
+            &["ID_INPUT_TABLET"],
+            &[],
+            None,
+        );
+        assert_eq!(pen.capabilities(), vec![Tablet]);
+        assert_eq!(pen.has_capability(Pointer), Some(false));
+
+        let unknown = EvdevDevice::from_parts("Unknown", 0x1234, 0x5678, &[], &[], None);
+        assert!(unknown.capabilities().is_empty());
+        assert_eq!(unknown.has_capability(Pointer), None);
+    }
+
+    #[test]
+    fn test_clickpad_vs_pressurepad() {
+        use Capability::*;
+
+        let touchpad = |vid: u16, pid: u16, props: &[InputProp]| {
+            EvdevDevice::from_parts("Touchpad", vid, pid, &["ID_INPUT_TOUCHPAD"], props, None)
+        };
+        // A hinged clickpad
+        let clickpad = touchpad(0x06cb, 0xcd8b, &[InputProp::Buttonpad]);
+        assert_eq!(clickpad.capabilities(), vec![Pointer, Touchpad, Clickpad]);
+        // Apple Magic Trackpad 2, the kernel only knows it's a clickpad
+        let pressurepad = touchpad(0x05ac, 0x0265, &[InputProp::Buttonpad]);
+        assert_eq!(
+            pressurepad.capabilities(),
+            vec![Pointer, Touchpad, Clickpad, Pressurepad]
+        );
+        // A touchpad with physical buttons
+        let buttons = touchpad(0x06cb, 0xcd8b, &[]);
+        assert_eq!(buttons.capabilities(), vec![Pointer, Touchpad]);
+    }
+
+    /// A device without udev, e.g. from a [`Capture`] taken without udev
+    #[derive(Default)]
+    struct TestSource {
+        name: String,
+        input_properties: Vec<u16>,
+        keys: Vec<u16>,
+        abs_axes: Vec<u16>,
+        rel_axes: Vec<u16>,
+    }
+
+    impl EvdevSource for TestSource {
+        fn name(&self) -> String {
+            self.name.clone()
+        }
+
+        fn input_id(&self) -> (u16, u16, u16) {
+            (0x03, 0x1234, 0x5678)
+        }
+
+        fn udev_properties(&self) -> Vec<String> {
+            Vec::new()
+        }
+
+        fn input_properties(&self) -> Vec<u16> {
+            self.input_properties.clone()
+        }
+
+        fn keys(&self) -> Vec<u16> {
+            self.keys.clone()
+        }
+
+        fn abs_axes(&self) -> Vec<u16> {
+            self.abs_axes.clone()
+        }
+
+        fn rel_axes(&self) -> Vec<u16> {
+            self.rel_axes.clone()
+        }
+
+        fn size_mm(&self) -> Option<(u32, u32)> {
+            None
+        }
+
+        fn devnode(&self) -> Option<PathBuf> {
+            None
+        }
+
+        fn sysfs_path(&self) -> Option<PathBuf> {
+            None
+        }
+    }
+
+    /// `KEY_ESC` to `KEY_SPACE`, the main block of a keyboard
+    const KEYBOARD_KEYS: std::ops::RangeInclusive<u16> = 1..=57;
+
+    #[test]
+    fn test_empty_name() {
+        let source = TestSource {
+            keys: KEYBOARD_KEYS.collect(),
+            ..Default::default()
+        };
+        let device = EvdevDevice::from_source(&source);
+        assert_eq!(device.name(), "");
+        assert_eq!(device.capabilities(), vec![Capability::Keyboard]);
+
+        let mut tree = DeviceTree::new();
+        let idx = tree.attach_evdev_device(device);
+        let pidx = tree.get_evdev_device(&idx).unwrap().parent();
+        let parent = tree.get_parent_device(&pidx).unwrap();
+        assert_eq!(parent.name(), "");
+        assert!(matches!(
+            parent.abstract_types()[..],
+            [AbstractType::Keyboard]
+        ));
+    }
+
+    /// The abstract type of the physical device of the given devices
+    fn parent_type(devices: Vec<EvdevDevice>) -> Option<AbstractType> {
+        let mut tree = DeviceTree::new();
+        let mut pidx = None;
+        for mut device in devices {
+            device.set_phys("usb-0000:00:14.0-1/input0");
+            let idx = tree.attach_evdev_device(device);
+            pidx = Some(tree.get_evdev_device(&idx).unwrap().parent());
+        }
+        let parent = tree.get_parent_device(&pidx?)?;
+        parent.abstract_types().first().cloned()
+    }
+
+    const INPUT_PROP_DIRECT: u16 = 0x01;
+
+    #[test]
+    fn test_stylus() {
+        let pen = || {
+            EvdevDevice::from_source(&TestSource {
+                name: "Pen".to_string(),
+                keys: vec![BTN_TOUCH, BTN_TOOL_PEN, BTN_STYLUS],
+                abs_axes: vec![ABS_X, ABS_Y],
+                ..Default::default()
+            })
+        };
+        assert_eq!(
+            pen().capabilities(),
+            vec![Capability::Tablet, Capability::Stylus]
+        );
+
+        // A Wacom Intuos, the pen and the pad
+        let pad =
+            EvdevDevice::from_parts("Pad", 0x1234, 0x5678, &["ID_INPUT_TABLET_PAD"], &[], None);
+        assert!(matches!(
+            parent_type(vec![pen(), pad]),
+            Some(AbstractType::Tablet)
+        ));
+
+        // A 2-in-1 with an active stylus is still a touchscreen
+        let touchscreen = EvdevDevice::from_source(&TestSource {
+            name: "Touchscreen".to_string(),
+            input_properties: vec![INPUT_PROP_DIRECT],
+            keys: vec![BTN_TOUCH],
+            abs_axes: vec![ABS_X, ABS_Y, ABS_MT_POSITION_X, ABS_MT_POSITION_Y],
+            ..Default::default()
+        });
+        assert_eq!(touchscreen.capabilities(), vec![Capability::Touchscreen]);
+        assert!(matches!(
+            parent_type(vec![touchscreen, pen()]),
+            Some(AbstractType::Touchscreen)
+        ));
+    }
+
+    #[test]
+    fn test_dial() {
+        const BTN_0: u16 = 0x100;
+
+        let dial = || {
+            EvdevDevice::from_source(&TestSource {
+                name: "Microsoft Surface Dial System Multi Axis".to_string(),
+                keys: vec![BTN_0],
+                rel_axes: vec![REL_DIAL],
+                ..Default::default()
+            })
+        };
+        assert_eq!(dial().capabilities(), vec![Capability::Dial]);
+        assert!(matches!(
+            parent_type(vec![dial()]),
+            Some(AbstractType::Dial)
+        ));
+
+        // Tagged as a mouse, but it does not move
+        let dial = EvdevDevice::from_parts("Dial", 0x1234, 0x5678, &["ID_INPUT_MOUSE"], &[], None);
+        let dial = with_bits(dial, &[BTN_0], &[REL_DIAL]);
+        assert_eq!(dial.capabilities(), vec![Capability::Dial]);
+
+        // A mouse has a wheel but is not a dial
+        let mouse = EvdevDevice::from_source(&TestSource {
+            name: "Mouse".to_string(),
+            keys: vec![BTN_MOUSE],
+            rel_axes: vec![REL_X, REL_Y, REL_WHEEL],
+            ..Default::default()
+        });
+        assert_eq!(mouse.capabilities(), vec![Capability::Pointer]);
+    }
+
+    #[test]
+    fn test_capabilities_sorted() {
+        use Capability::*;
+
+        let device = EvdevDevice::from_parts(
+            "Wacom ArtPad",
+            0x1234,
+            0x5678,
+            &["ID_INPUT_TABLET_JOYSTICK", "ID_INPUT_MOUSE", "ID_INPUT_KEY"],
+            &[],
+            None,
+        );
+        assert_eq!(
+            device.capabilities(),
+            vec![Keyboard, Pointer, Joystick, Tablet]
+        );
+    }
+
+    #[test]
+    fn test_tablet_joystick() {
+        use Capability::*;
+
+        // What udev tags an old Wacom ArtPad with
+        let artpad = || {
+            EvdevDevice::from_parts(
+                "Wacom ArtPad",
+                0x1234,
+                0x5678,
+                &["ID_INPUT", "ID_INPUT_TABLET_JOYSTICK"],
+                &[],
+                None,
+            )
+        };
+        assert_eq!(artpad().capabilities(), vec![Joystick, Tablet]);
+        assert!(matches!(
+            parent_type(vec![artpad()]),
+            Some(AbstractType::Tablet)
+        ));
+
+        let joystick = EvdevDevice::from_parts(
+            "Joystick",
+            0x1234,
+            0x5678,
+            &["ID_INPUT", "ID_INPUT_JOYSTICK"],
+            &[],
+            None,
+        );
+        assert_eq!(joystick.capabilities(), vec![Joystick]);
+        assert!(matches!(
+            parent_type(vec![joystick]),
+            Some(AbstractType::GamingDevice)
+        ));
+    }
+
+    #[test]
+    fn test_accelerometer() {
+        const INPUT_PROP_ACCELEROMETER: u16 = 0x06;
+        const ABS_Z: u16 = 0x02;
+
+        // A laptop's orientation sensor
+        let sensor = || {
+            EvdevDevice::from_parts(
+                "Accelerometer",
+                0x1234,
+                0x5678,
+                &["ID_INPUT_ACCELEROMETER"],
+                &[],
+                None,
+            )
+        };
+        assert_eq!(sensor().capabilities(), vec![Capability::Accelerometer]);
+        assert!(!sensor().has(Capability::Pointer));
+        assert!(!sensor().has(Capability::Keyboard));
+        assert!(matches!(
+            parent_type(vec![sensor()]),
+            Some(AbstractType::Sensor)
+        ));
+
+        // Without udev only the kernel tells us
+        let sensor = EvdevDevice::from_source(&TestSource {
+            name: "Accelerometer".to_string(),
+            input_properties: vec![INPUT_PROP_ACCELEROMETER],
+            abs_axes: vec![ABS_X, ABS_Y, ABS_Z],
+            ..Default::default()
+        });
+        assert_eq!(sensor.capabilities(), vec![Capability::Accelerometer]);
+
+        // The motion sensors of a controller
+        let motion = EvdevDevice::from_parts(
+            "Controller Motion Sensors",
+            0x1234,
+            0x5678,
+            &["ID_INPUT_ACCELEROMETER"],
+            &[],
+            None,
+        );
+        let controller = EvdevDevice::from_parts(
+            "Controller",
+            0x1234,
+            0x5678,
+            &["ID_INPUT_JOYSTICK"],
+            &[],
+            None,
+        );
+        assert!(matches!(
+            parent_type(vec![motion, controller]),
+            Some(AbstractType::GamingDevice)
+        ));
+    }
+
+    #[test]
+    fn test_capabilities_from_bits() {
+        use Capability::*;
+
+        // A name that doesn't tell us anything, see Capability::from_name
+        let device = |props: &[u16], keys: &[u16], abs_axes: &[u16], rel_axes: &[u16]| {
+            EvdevDevice::from_source(&TestSource {
+                name: "Generic Device".to_string(),
+                input_properties: props.to_vec(),
+                keys: keys.to_vec(),
+                abs_axes: abs_axes.to_vec(),
+                rel_axes: rel_axes.to_vec(),
+            })
+        };
+        const BTN_RIGHT: u16 = 0x111;
+        const BTN_MIDDLE: u16 = 0x112;
+        const BTN_EAST: u16 = 0x131;
+        const BTN_NORTH: u16 = 0x133;
+        const BTN_WEST: u16 = 0x134;
+        let buttons = [BTN_MOUSE, BTN_RIGHT, BTN_MIDDLE];
+        let mt = [ABS_X, ABS_Y, ABS_MT_POSITION_X, ABS_MT_POSITION_Y];
+
+        let mouse = device(&[], &buttons, &[], &[REL_X, REL_Y, REL_WHEEL]);
+        assert_eq!(mouse.capabilities(), vec![Pointer]);
+        assert_eq!(mouse.sources(), &vec![Source::Kernel]);
+
+        let keys: Vec<u16> = KEYBOARD_KEYS.collect();
+        let keyboard = device(&[], &keys, &[], &[]);
+        assert_eq!(keyboard.capabilities(), vec![Keyboard]);
+
+        let touchpad = device(&[], &[BTN_MOUSE, BTN_TOOL_FINGER, BTN_TOUCH], &mt, &[]);
+        assert_eq!(touchpad.capabilities(), vec![Pointer, Touchpad]);
+
+        let touchscreen = device(&[INPUT_PROP_DIRECT], &[BTN_TOUCH], &mt, &[]);
+        assert_eq!(touchscreen.capabilities(), vec![Touchscreen]);
+
+        let tablet = device(&[], &[BTN_TOUCH, BTN_TOOL_PEN], &[ABS_X, ABS_Y], &[]);
+        assert_eq!(tablet.capabilities(), vec![Tablet, Stylus]);
+
+        let gamepad = device(
+            &[],
+            &[BTN_GAMEPAD, BTN_EAST, BTN_NORTH, BTN_WEST],
+            &[ABS_X, ABS_Y],
+            &[],
+        );
+        assert_eq!(gamepad.capabilities(), vec![Joystick, Gamepad]);
+
+        let nothing = device(&[], &[], &[], &[]);
+        assert!(nothing.capabilities().is_empty());
+    }
+
+    #[test]
+    fn test_numpad() {
+        const KEY_BACKSPACE: u16 = 14;
+        const KEY_NUMLOCK: u16 = 69;
+        const KEY_KPENTER: u16 = 96;
+        let keyboard = |keys: Vec<u16>| {
+            EvdevDevice::from_source(&TestSource {
+                name: "USB Keyboard".to_string(),
+                keys,
+                ..Default::default()
+            })
+        };
+        let device_type = |device: EvdevDevice| {
+            let mut parent = PhysicalDevice::new();
+            parent.merge_capabilities(&AttachedDevice::Evdev(device));
+            parent.device_type()
+        };
+
+        // KEY_KP7 to KEY_KPDOT, plus the usual extras
+        let mut keys: Vec<u16> = (71..=83).collect();
+        keys.extend([KEY_BACKSPACE, KEY_NUMLOCK, KEY_KPENTER]);
+        let numpad = keyboard(keys);
+        assert_eq!(
+            numpad.capabilities(),
+            vec![Capability::Keyboard, Capability::Numpad]
+        );
+        assert_eq!(device_type(numpad), Some(DeviceType::Numpad));
+
+        // A full keyboard with a numpad, KEY_ESC to KEY_KPDOT
+        let full = keyboard((1..=83).collect());
+        assert_eq!(full.capabilities(), vec![Capability::Keyboard]);
+        assert_eq!(device_type(full), Some(DeviceType::Keyboard));
+
+        // A keyboard without a numpad isn't one either
+        let tkl = keyboard(KEYBOARD_KEYS.collect());
+        assert_eq!(tkl.capabilities(), vec![Capability::Keyboard]);
+    }
+}
@@ -1,37 +1,440 @@
 use crate::*;
 
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "udev")]
+use std::ffi::CStr;
+#[cfg(feature = "udev")]
+use std::os::fd::AsRawFd;
+use std::path::PathBuf;
+
+/// `struct hidraw_devinfo` from `linux/hidraw.h`
+#[cfg(feature = "udev")]
+#[repr(C)]
+#[derive(Default)]
+struct HidrawDevinfo {
+    bustype: u32,
+    vendor: i16,
+    product: i16,
+}
+
+#[cfg(feature = "udev")]
+const HIDIOCGRAWINFO: libc::c_ulong =
+    util::ioc_read(b'H', 0x03, std::mem::size_of::<HidrawDevinfo>());
+
+#[cfg(feature = "udev")]
+const fn hidiocgrawname(len: usize) -> libc::c_ulong {
+    util::ioc_read(b'H', 0x04, len)
+}
+
+/// `HID_MAX_DESCRIPTOR_SIZE` from `linux/hid.h`
+const HID_MAX_DESCRIPTOR_SIZE: usize = 4096;
+
+/// `struct hidraw_report_descriptor` from `linux/hidraw.h`
+#[cfg(feature = "udev")]
+#[repr(C)]
+struct HidrawReportDescriptor {
+    size: u32,
+    value: [u8; HID_MAX_DESCRIPTOR_SIZE],
+}
+
+#[cfg(feature = "udev")]
+const HIDIOCGRDESCSIZE: libc::c_ulong =
+    util::ioc_read(b'H', 0x01, std::mem::size_of::<libc::c_int>());
+#[cfg(feature = "udev")]
+const HIDIOCGRDESC: libc::c_ulong =
+    util::ioc_read(b'H', 0x02, std::mem::size_of::<HidrawReportDescriptor>());
+
+/// A HID usage, i.e. the usage page in the upper 16 bits and the usage
+/// id in the lower 16 bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct Usage(u32);
+
+impl Usage {
+    const GD_POINTER: Usage = Usage(0x0001_0001);
+    const GD_MOUSE: Usage = Usage(0x0001_0002);
+    const GD_JOYSTICK: Usage = Usage(0x0001_0004);
+    const GD_GAMEPAD: Usage = Usage(0x0001_0005);
+    const GD_KEYBOARD: Usage = Usage(0x0001_0006);
+    const GD_KEYPAD: Usage = Usage(0x0001_0007);
+    /// The Surface Dial and similar dials are System Multi-Axis Controllers
+    const GD_MULTI_AXIS_CONTROLLER: Usage = Usage(0x0001_000e);
+    const GD_SYSTEM_CONTROL: Usage = Usage(0x0001_0080);
+    const SIM_AUTOMOBILE: Usage = Usage(0x0002_0002);
+    const DIG_DIGITIZER: Usage = Usage(0x000d_0001);
+    const DIG_PEN: Usage = Usage(0x000d_0002);
+    const DIG_TOUCHSCREEN: Usage = Usage(0x000d_0004);
+    const DIG_TOUCHPAD: Usage = Usage(0x000d_0005);
+    const CONSUMER_CONTROL: Usage = Usage(0x000c_0001);
+
+    /// The capabilities a top-level application collection of this usage represents
+    fn capabilities(&self) -> &'static [Capability] {
+        match *self {
+            Usage::GD_POINTER | Usage::GD_MOUSE => &[Capability::Pointer],
+            Usage::GD_JOYSTICK => &[Capability::Joystick],
+            Usage::GD_GAMEPAD => &[Capability::Gamepad],
+            Usage::GD_MULTI_AXIS_CONTROLLER => &[Capability::Dial],
+            Usage::GD_KEYBOARD | Usage::GD_KEYPAD | Usage::GD_SYSTEM_CONTROL => {
+                &[Capability::Keyboard]
+            }
+            Usage::SIM_AUTOMOBILE => &[Capability::RacingWheel],
+            Usage::DIG_DIGITIZER => &[Capability::Tablet],
+            Usage::DIG_PEN => &[Capability::Tablet, Capability::Stylus],
+            Usage::DIG_TOUCHSCREEN => &[Capability::Touchscreen],
+            Usage::DIG_TOUCHPAD => &[Capability::Touchpad],
+            Usage::CONSUMER_CONTROL => &[Capability::Keyboard],
+            _ => &[],
+        }
+    }
+
+    /// The [`Application`] a top-level application collection of this usage represents
+    fn application(&self) -> Option<Application> {
+        match *self {
+            Usage::GD_POINTER | Usage::GD_MOUSE => Some(Application::Mouse),
+            Usage::GD_JOYSTICK => Some(Application::Joystick),
+            Usage::GD_GAMEPAD => Some(Application::Gamepad),
+            Usage::GD_KEYBOARD => Some(Application::Keyboard),
+            Usage::GD_KEYPAD => Some(Application::Keypad),
+            Usage::GD_SYSTEM_CONTROL => Some(Application::SystemControl),
+            Usage::DIG_DIGITIZER | Usage::DIG_PEN => Some(Application::Pen),
+            Usage::DIG_TOUCHSCREEN => Some(Application::Touchscreen),
+            Usage::DIG_TOUCHPAD => Some(Application::Touchpad),
+            Usage::CONSUMER_CONTROL => Some(Application::ConsumerControl),
+            _ => None,
+        }
+    }
+}
+
+/// Walk the HID report descriptor and return the usages of all
+/// top-level application collections, in the order they appear.
+///
+/// Only the items required to track usages and collections are
+/// interpreted, everything else is skipped. A truncated descriptor
+/// returns the collections found up to that point.
+fn parse_report_descriptor(rdesc: &[u8]) -> Vec<Usage> {
+    let mut applications = Vec::new();
+    let mut usage_page: u32 = 0;
+    let mut page_stack: Vec<u32> = Vec::new();
+    let mut usages: Vec<Usage> = Vec::new();
+    let mut depth = 0;
+
+    let mut data = rdesc;
+    while let Some((&prefix, rest)) = data.split_first() {
+        // Long items: 0xfe, data size, tag, data
+        if prefix == 0xfe {
+            let len = rest.first().map(|l| *l as usize + 2).unwrap_or(rest.len());
+            data = rest.get(len..).unwrap_or(&[]);
+            continue;
+        }
+
+        let len = match prefix & 0x3 {
+            3 => 4,
+            n => n as usize,
+        };
+        let Some(bytes) = rest.get(..len) else {
+            break;
+        };
+        data = &rest[len..];
+        let value = bytes
+            .iter()
+            .rev()
+            .fold(0u32, |acc, b| (acc << 8) | *b as u32);
+
+        let itype = (prefix >> 2) & 0x3;
+        let tag = prefix >> 4;
+        match (itype, tag) {
+            // Main: Collection
+            (0, 0xa) => {
+                // 0x01 is an Application collection
+                if depth == 0 && value == 0x01 {
+                    if let Some(usage) = usages.first() {
+                        applications.push(*usage);
+                    }
+                }
+                depth += 1;
+                usages.clear();
+            }
+            // Main: End Collection
+            (0, 0xc) => {
+                depth = std::cmp::max(depth, 1) - 1;
+                usages.clear();
+            }
+            // Main: Input, Output, Feature
+            (0, _) => usages.clear(),
+            // Global: Usage Page
+            (1, 0x0) => usage_page = value,
+            // Global: Push, Pop
+            (1, 0xa) => page_stack.push(usage_page),
+            (1, 0xb) => usage_page = page_stack.pop().unwrap_or(usage_page),
+            // Local: Usage, extended usages include the usage page
+            (2, 0x0) if len == 4 => usages.push(Usage(value)),
+            (2, 0x0) => usages.push(Usage((usage_page << 16) | value)),
+            _ => {}
+        }
+    }
+
+    applications
+}
+
 /// The [`HidrawDevice`] struct represents a single kernel device and
 /// the queryable information about this device.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HidrawDevice {
     /// Attachment in the [`DeviceTree`]
-    node: Option<Node>,
+    #[serde(skip, default = "Node::new")]
+    node: Node,
+    name: String,
+    bustype: BusType,
+    vid: u16,
+    pid: u16,
+    devnode: Option<PathBuf>,
+    sysfs: PathBuf,
+    applications: Vec<Usage>,
+    capabilities: Vec<Capability>,
+}
+
+/// Two [`HidrawDevice`]s are equal if they have the same [`DeviceIndex`], i.e. a
+/// clone is equal to the device it was cloned from.
+impl PartialEq for HidrawDevice {
+    fn eq(&self, other: &Self) -> bool {
+        self.index() == other.index()
+    }
 }
 
 impl HasParent for HidrawDevice {
-    fn parent(&self) -> DeviceIndex {
-        assert!(self.node.is_some());
-        self.node.unwrap().parent.unwrap().clone()
+    fn try_parent(&self) -> Option<DeviceIndex> {
+        self.node.parent
+    }
+}
+
+impl<'a> HidrawDevice {
+    /// Return a new [`HidrawDevice`] based on the device that the fd points to.
+    /// The fd must be ready for `ioctl()` no data is read or written on this fd.
+    #[cfg(feature = "udev")]
+    pub fn from_fd(fd: OwnedFd) -> Result<HidrawDevice, Error> {
+        // Get st_rdev from the fd so we can later look this up with udev
+        let f = File::from(fd);
+        let meta = f.metadata()?;
+        let rdev = meta.st_rdev();
+
+        // The device may have been removed since the fd was opened
+        let device = util::udev_device_by_devnum("hidraw", "hidraw*", rdev)?;
+
+        Self::from_udev(f, &device)
+    }
+
+    /// Return a new [`HidrawDevice`] for the opened device node and its
+    /// udev device, e.g. where the caller already scanned udev.
+    #[cfg(feature = "udev")]
+    pub(crate) fn from_udev(
+        f: File,
+        device: &udev::Device,
+    ) -> Result<HidrawDevice, Error> {
+        let devnode = device.devnode().map(|n| n.to_owned());
+        let sysfs = device.syspath().to_path_buf();
+
+        let mut info = HidrawDevinfo::default();
+        let rc = unsafe { libc::ioctl(f.as_raw_fd(), HIDIOCGRAWINFO as _, &mut info) };
+        if rc < 0 {
+            return Err(Error::last_ioctl_error("HIDIOCGRAWINFO"));
+        }
+
+        let mut buf = [0u8; 256];
+        let rc = unsafe {
+            libc::ioctl(
+                f.as_raw_fd(),
+                hidiocgrawname(buf.len()) as _,
+                buf.as_mut_ptr(),
+            )
+        };
+        if rc < 0 {
+            return Err(Error::last_ioctl_error("HIDIOCGRAWNAME"));
+        }
+        let name = CStr::from_bytes_until_nul(&buf)
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let rdesc = report_descriptor(&f)?;
+        let ids = (
+            BusType::from_raw(info.bustype as u16),
+            info.vendor as u16,
+            info.product as u16,
+        );
+
+        Ok(Self::from_parts(name, ids, devnode, sysfs, &rdesc))
+    }
+
+    /// Return a new [`HidrawDevice`] for the udev device from sysfs and the
+    /// udev properties only, without opening the device node.
+    #[cfg(feature = "udev")]
+    pub(crate) fn from_udev_sysfs(device: &udev::Device) -> Result<HidrawDevice, Error> {
+        let not_hidraw = || Error::NotAnInputDevice(device.syspath().to_path_buf());
+        // The name, ids and report descriptor are on the hid parent
+        let hid = device.parent().ok_or_else(not_hidraw)?;
+        let prop = |name: &str| {
+            hid.property_value(name)
+                .and_then(|v| v.to_str())
+                .map(String::from)
+        };
+        let name = prop("HID_NAME").unwrap_or_default();
+        // HID_ID=0003:0000046D:0000C52B
+        let ids: Vec<u32> = prop("HID_ID")
+            .ok_or_else(not_hidraw)?
+            .split(':')
+            .filter_map(|id| u32::from_str_radix(id, 16).ok())
+            .collect();
+        let ids = match ids[..] {
+            [bustype, vid, pid] => (BusType::from_raw(bustype as u16), vid as u16, pid as u16),
+            _ => return Err(not_hidraw()),
+        };
+        let rdesc = std::fs::read(hid.syspath().join("report_descriptor"))?;
+
+        Ok(Self::from_parts(
+            name,
+            ids,
+            device.devnode().map(|n| n.to_owned()),
+            device.syspath().to_path_buf(),
+            &rdesc,
+        ))
+    }
+
+    /// Create a device from its parts, the capabilities are derived from
+    /// the report descriptor.
+    pub(crate) fn from_parts(
+        name: String,
+        (bustype, vid, pid): (BusType, u16, u16),
+        devnode: Option<PathBuf>,
+        sysfs: PathBuf,
+        rdesc: &[u8],
+    ) -> HidrawDevice {
+        let applications = parse_report_descriptor(rdesc);
+        let capabilities = applications
+            .iter()
+            .flat_map(|usage| usage.capabilities().iter().copied())
+            .collect();
+
+        Self {
+            node: Node::new(),
+            name,
+            bustype,
+            vid,
+            pid,
+            devnode,
+            sysfs,
+            applications,
+            capabilities: Capability::extend(capabilities),
+        }
+    }
+
+    /// Return the device's name as advertised by the kernel.
+    pub fn name(&'a self) -> &'a str {
+        &self.name
+    }
+
+    /// The bus this device is connected through
+    pub fn bustype(&self) -> BusType {
+        self.bustype
+    }
+
+    /// The 16-bit Vendor ID
+    pub fn vid(&self) -> u16 {
+        self.vid
+    }
+
+    /// The 16-bit Product ID
+    pub fn pid(&self) -> u16 {
+        self.pid
+    }
+
+    pub fn devnode(&self) -> &Option<PathBuf> {
+        &self.devnode
+    }
+
+    pub fn sysfs_path(&self) -> &PathBuf {
+        &self.sysfs
+    }
+
+    /// Returns true if this is a virtual device, e.g. one created via `uhid`,
+    /// see [`DeviceTree::skip_virtual`].
+    pub fn is_virtual(&self) -> bool {
+        util::is_virtual(&self.sysfs)
+    }
+
+    pub(crate) fn set_parent(&mut self, parent: DeviceIndex) {
+        self.node.set_parent(parent);
+    }
+
+    pub(crate) fn set_node(&mut self, node: Node) {
+        self.node = node;
+    }
+
+    pub(crate) fn index(&self) -> DeviceIndex {
+        self.node.idx.clone()
+    }
+
+    /// Return the HID applications of this device, i.e. the top-level
+    /// application collections in the device's HID report descriptor, in
+    /// the order they appear in the descriptor.
+    ///
+    /// This is a feature of the Linux kernel that HID devices are split
+    /// across various evdev nodes, typically by HID Application. For example
+    /// a mouse device is often split into a [`Application::Mouse`] and
+    /// a [`Application::Keyboard`] device, so the applications returned here
+    /// are a good indication of the evdev nodes this device will create.
+    ///
+    /// Application collections without a matching [`Application`] are skipped.
+    pub fn hid_applications(&self) -> Vec<Application> {
+        self.applications
+            .iter()
+            .filter_map(|usage| usage.application())
+            .collect()
     }
 }
 
-impl HidrawDevice {
-    // /// Return the HID application this device is mapped to.
-    // /// This is a feature of the Linux kernel that HID devices are split
-    // /// across various evdev nodes, typically by HID Application. For example
-    // /// a mouse device is often split into a [`Application::Mouse`] and
-    // /// a [`Application::Keyboard`] device.
-    // ///
-    // /// Where a device originates from an evdev node (see [`Builder::evdev_fd`])
-    // /// this function returns the application that the evdev node represents, if any.
-    // /// Otherwise, this function returns None.
-    // pub fn hid_application(self) -> Option<Application> {
-    //     None
-    // }
+impl HasCapability for HidrawDevice {
+    /// The capabilities of a hidraw device are derived from the top-level
+    /// application collections in its HID report descriptor.
+    fn capabilities(&self) -> Vec<Capability> {
+        let mut capabilities = self.capabilities.clone();
+        capabilities.sort();
+        capabilities
+    }
+}
+
+impl Device for HidrawDevice {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn physical_parent(&self) -> Option<DeviceIndex> {
+        self.try_parent()
+    }
+}
+
+/// Read the HID report descriptor from the hidraw device.
+#[cfg(feature = "udev")]
+fn report_descriptor(f: &File) -> Result<Vec<u8>, Error> {
+    let mut size: libc::c_int = 0;
+    let rc = unsafe { libc::ioctl(f.as_raw_fd(), HIDIOCGRDESCSIZE as _, &mut size) };
+    if rc < 0 {
+        return Err(Error::last_ioctl_error("HIDIOCGRDESCSIZE"));
+    }
+
+    let mut rdesc = HidrawReportDescriptor {
+        size: std::cmp::min(size as usize, HID_MAX_DESCRIPTOR_SIZE) as u32,
+        value: [0u8; HID_MAX_DESCRIPTOR_SIZE],
+    };
+    let rc = unsafe { libc::ioctl(f.as_raw_fd(), HIDIOCGRDESC as _, &mut rdesc) };
+    if rc < 0 {
+        return Err(Error::last_ioctl_error("HIDIOCGRDESC"));
+    }
+
+    Ok(rdesc.value[..rdesc.size as usize].to_vec())
 }
 
 /// The Linux kernel splits HID devices up by application and a single
 /// HID device may result in multiple evdev nodes.
 #[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Application {
     Mouse,
     Touchpad,
@@ -39,4 +442,87 @@ pub enum Application {
     Keypad,
     ConsumerControl,
     SystemControl,
+    Joystick,
+    Gamepad,
+    Pen,
+    Touchscreen,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A wireless mouse with extra keyboard and consumer control applications
+    /// for its macro keys
+    const MOUSE_RDESC: &[u8] = &[
+        0x05, 0x01, 0x09, 0x02, 0xa1, 0x01, 0x09, 0x01, 0xa1, 0x00, 0x05, 0x09, 0x19, 0x01, 0x29,
+        0x03, 0x15, 0x00, 0x25, 0x01, 0x95, 0x03, 0x75, 0x01, 0x81, 0x02, 0x95, 0x01, 0x75, 0x05,
+        0x81, 0x01, 0x05, 0x01, 0x09, 0x30, 0x09, 0x31, 0x09, 0x38, 0x15, 0x81, 0x25, 0x7f, 0x75,
+        0x08, 0x95, 0x03, 0x81, 0x06, 0xc0, 0xc0, 0x05, 0x01, 0x09, 0x06, 0xa1, 0x01, 0x05, 0x07,
+        0x19, 0xe0, 0x29, 0xe7, 0x15, 0x00, 0x25, 0x01, 0x75, 0x01, 0x95, 0x08, 0x81, 0x02, 0x95,
+        0x06, 0x75, 0x08, 0x15, 0x00, 0x25, 0x65, 0x19, 0x00, 0x29, 0x65, 0x81, 0x00, 0xc0, 0x05,
+        0x0c, 0x09, 0x01, 0xa1, 0x01, 0x85, 0x03, 0x15, 0x00, 0x26, 0xff, 0x02, 0x19, 0x00, 0x2a,
+        0xff, 0x02, 0x75, 0x10, 0x95, 0x01, 0x81, 0x00, 0xc0,
+    ];
+
+    fn device(rdesc: &[u8]) -> HidrawDevice {
+        HidrawDevice::from_parts(
+            "Logitech USB Receiver".to_string(),
+            (BusType::Usb, 0x046d, 0xc52b),
+            None,
+            PathBuf::from("/sys/devices/virtual/hidraw/hidraw0"),
+            rdesc,
+        )
+    }
+
+    #[test]
+    fn test_parse_report_descriptor() {
+        assert_eq!(
+            parse_report_descriptor(MOUSE_RDESC),
+            vec![Usage::GD_MOUSE, Usage::GD_KEYBOARD, Usage::CONSUMER_CONTROL]
+        );
+
+        let device = device(MOUSE_RDESC);
+        assert_eq!(
+            device.hid_applications(),
+            vec![
+                Application::Mouse,
+                Application::Keyboard,
+                Application::ConsumerControl
+            ]
+        );
+        assert_eq!(
+            device.capabilities(),
+            vec![Capability::Keyboard, Capability::Pointer]
+        );
+    }
+
+    #[test]
+    fn test_parse_report_descriptor_items() {
+        // A pen with an extended usage and the usage page pushed and popped
+        let rdesc = &[
+            0x05, 0x01, 0xa4, 0x05, 0x0d, 0xb4, 0x0b, 0x02, 0x00, 0x0d, 0x00, 0xa1, 0x01, 0x09,
+            0x42, 0x81, 0x02, 0xc0,
+        ];
+        assert_eq!(parse_report_descriptor(rdesc), vec![Usage::DIG_PEN]);
+        assert_eq!(
+            device(rdesc).capabilities(),
+            vec![Capability::Tablet, Capability::Stylus]
+        );
+
+        // Physical collections and nested applications don't count
+        let rdesc = &[
+            0x05, 0x0d, 0x09, 0x05, 0xa1, 0x00, 0xc0, 0x09, 0x04, 0xa1, 0x01, 0x09, 0x02, 0xa1,
+            0x01, 0xc0, 0xc0,
+        ];
+        assert_eq!(parse_report_descriptor(rdesc), vec![Usage::DIG_TOUCHSCREEN]);
+
+        // A truncated descriptor returns what we have so far
+        assert_eq!(
+            parse_report_descriptor(&MOUSE_RDESC[..60]),
+            vec![Usage::GD_MOUSE, Usage::GD_KEYBOARD]
+        );
+        assert!(parse_report_descriptor(&[]).is_empty());
+        assert!(device(&[0x05]).capabilities().is_empty());
+    }
 }
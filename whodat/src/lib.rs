@@ -1,56 +1,204 @@
 #![doc = include_str!("../../README.md")]
 #![allow(unused_variables, dead_code)]
 
-use evdev;
+use serde::{Deserialize, Serialize};
 use std::{
     cmp::PartialEq,
     collections::HashMap,
-    error::Error,
-    fs::File,
+    error::Error as StdError,
     hash::{Hash, Hasher},
-    os::fd::OwnedFd,
-    os::linux::fs::MetadataExt,
-    sync::atomic::{AtomicU32, Ordering},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
 };
-use udev;
+#[cfg(feature = "udev")]
+use std::{fs::File, os::fd::OwnedFd, os::linux::fs::MetadataExt};
 
+mod builder;
+mod capture;
+mod error;
 mod evdev_device;
 mod hidraw_device;
+#[cfg(feature = "udev")]
+mod monitor;
 mod physical_device;
+mod proc_devices;
+mod quirks;
+mod serialize;
 mod types;
 mod util;
 
+pub use builder::Builder;
+pub use capture::{Capture, EvdevSource};
+pub use error::Error;
 pub use evdev_device::EvdevDevice;
-pub use hidraw_device::HidrawDevice;
+pub use hidraw_device::{Application, HidrawDevice};
+#[cfg(feature = "udev")]
+pub use monitor::{DeviceMonitor, MonitorEvent};
 pub use physical_device::PhysicalDevice;
-pub use types::{AbstractType, Capability};
+pub use proc_devices::ProcInputDevice;
+pub use quirks::Quirk;
+pub use serialize::FORMAT_VERSION;
+pub use types::{
+    AbsResolution, AbstractType, BusType, Capability, CapabilitySet, DeviceType, GamepadProfile,
+    InputProp, Source, SwitchType, TabletPadLayout,
+};
 
 // Next device id, see [`DeviceIndex::next`]
 static NEXT_ID: AtomicU32 = AtomicU32::new(1);
 
+/// The subsystems and sysnames of the device nodes we attach, see
+/// [`DeviceTree::udev_device`]. Only the device nodes we handle, not every
+/// `inputN` or `mouseN` device.
+#[cfg(feature = "udev")]
+const UDEV_NODES: &[(&str, &str)] = &[("input", "event*"), ("hidraw", "hidraw*")];
+
 /// The generic return type for [`DeviceTree::get_device`].
+///
+/// The [`DeviceTree`] owns all devices, each [`AttachedDevice`] lives in the
+/// tree until it is removed. [`DeviceTree::get_device`], [`DeviceTree::iter`]
+/// and [`DeviceTree::children`] only ever hand out references to it. A
+/// [`PhysicalDevice`] does not own its children either, it only stores their
+/// [`DeviceIndex`], see [`PhysicalDevice::iter`].
+///
+/// A device can be cloned to take a snapshot out of the tree. Devices compare
+/// equal if they have the same [`DeviceIndex`], so a snapshot is equal to the
+/// device in the tree it was taken from.
 #[non_exhaustive]
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum AttachedDevice {
     Evdev(EvdevDevice),
+    Hidraw(HidrawDevice),
     Parent(PhysicalDevice),
 }
 
 impl AttachedDevice {
-    fn set_parent(&mut self, parent: &PhysicalDevice) {
+    /// Returns the [`EvdevDevice`] if this is an evdev device.
+    ///
+    /// ```
+    /// use whodat::{Builder, DeviceTree};
+    ///
+    /// let device = Builder::new().name("Generic Mouse").build().unwrap();
+    /// let mut tree = DeviceTree::new();
+    /// let idx = tree.attach_evdev_device(device);
+    /// let device = tree.get_device(&idx).unwrap();
+    /// assert_eq!(device.as_evdev().unwrap().name(), "Generic Mouse");
+    /// assert!(device.as_parent().is_none());
+    /// ```
+    pub fn as_evdev(&self) -> Option<&EvdevDevice> {
+        match self {
+            AttachedDevice::Evdev(evdev) => Some(evdev),
+            _ => None,
+        }
+    }
+
+    /// Returns the [`HidrawDevice`] if this is a hidraw device.
+    pub fn as_hidraw(&self) -> Option<&HidrawDevice> {
+        match self {
+            AttachedDevice::Hidraw(hidraw) => Some(hidraw),
+            _ => None,
+        }
+    }
+
+    /// Returns the [`PhysicalDevice`] if this is a parent device.
+    ///
+    /// ```
+    /// use whodat::{Builder, DeviceTree, HasParent};
+    ///
+    /// let device = Builder::new().name("Generic Mouse").build().unwrap();
+    /// let mut tree = DeviceTree::new();
+    /// let idx = tree.attach_evdev_device(device);
+    /// let parent = tree.get_device(&idx).unwrap().as_evdev().unwrap().parent();
+    /// let parent = tree.get_device(&parent).unwrap();
+    /// assert!(parent.is_parent());
+    /// println!("Parent device is {}", parent.as_parent().unwrap().name());
+    /// ```
+    pub fn as_parent(&self) -> Option<&PhysicalDevice> {
+        match self {
+            AttachedDevice::Parent(parent) => Some(parent),
+            _ => None,
+        }
+    }
+
+    /// Returns true if this is an evdev device, see [`AttachedDevice::as_evdev`].
+    pub fn is_evdev(&self) -> bool {
+        self.as_evdev().is_some()
+    }
+
+    /// Returns true if this is a hidraw device, see [`AttachedDevice::as_hidraw`].
+    pub fn is_hidraw(&self) -> bool {
+        self.as_hidraw().is_some()
+    }
+
+    /// Returns true if this is a parent device, see [`AttachedDevice::as_parent`].
+    pub fn is_parent(&self) -> bool {
+        self.as_parent().is_some()
+    }
+
+    /// Returns this device as a [`Device`], regardless of its type. An
+    /// [`AttachedDevice`] also implements [`HasCapability`] directly.
+    pub fn as_device(&self) -> &dyn Device {
+        match self {
+            AttachedDevice::Evdev(evdev) => evdev,
+            AttachedDevice::Hidraw(hidraw) => hidraw,
+            AttachedDevice::Parent(parent) => parent,
+        }
+    }
+
+    fn set_parent(&mut self, parent: DeviceIndex) {
         match self {
             AttachedDevice::Evdev(evdev) => {
                 evdev.set_parent(parent);
             }
+            AttachedDevice::Hidraw(hidraw) => {
+                hidraw.set_parent(parent);
+            }
             AttachedDevice::Parent(_) => {
                 panic!("Cannot set a parent to a parent");
             }
         }
     }
+
+    fn set_node(&mut self, node: Node) {
+        match self {
+            AttachedDevice::Evdev(evdev) => evdev.set_node(node),
+            AttachedDevice::Hidraw(hidraw) => hidraw.set_node(node),
+            AttachedDevice::Parent(parent) => parent.set_node(node),
+        }
+    }
+
+    fn index(&self) -> DeviceIndex {
+        match self {
+            AttachedDevice::Evdev(evdev) => evdev.index(),
+            AttachedDevice::Hidraw(hidraw) => hidraw.index(),
+            AttachedDevice::Parent(parent) => parent.index(),
+        }
+    }
+}
+
+impl PartialEq for AttachedDevice {
+    fn eq(&self, other: &Self) -> bool {
+        self.index() == other.index()
+    }
 }
 
 /// A unique device index to fetch a device from a [`DeviceTree`].
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+///
+/// Every device, kernel devices and [`PhysicalDevice`]s alike, is assigned
+/// an index when it is created. Indices are unique within the process and
+/// an index is never reused, not even after its device was removed from the
+/// tree (see [`DeviceTree::remove`]). The index of a removed device simply no
+/// longer resolves via [`DeviceTree::get_device`]. A caller may thus use an
+/// index as key to cache information about a device.
+///
+/// Indices are assigned in increasing order but they are not contiguous and
+/// they are not stable across processes, with the exception of a tree
+/// restored via [`DeviceTree::deserialize`] which keeps its indices.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct DeviceIndex {
     idx: u32,
 }
@@ -62,6 +210,18 @@ impl DeviceIndex {
             idx: NEXT_ID.fetch_add(1, Ordering::Relaxed),
         }
     }
+
+    /// Returns the numeric value of this index. The value is unique the same
+    /// way the index itself is, e.g. for use in a D-Bus object path.
+    pub fn as_u32(&self) -> u32 {
+        self.idx
+    }
+}
+
+impl std::fmt::Display for DeviceIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.idx)
+    }
 }
 
 impl Hash for DeviceIndex {
@@ -70,7 +230,10 @@ impl Hash for DeviceIndex {
     }
 }
 
-/// A node in the [`DeviceTree`].
+/// A node in the [`DeviceTree`], embedded in every device. The node holds
+/// the device's own [`DeviceIndex`] and, for kernel devices, the index of
+/// the [`PhysicalDevice`] parent, see [`HasParent`]. Nodes are internal,
+/// callers only ever see the indices.
 #[derive(Clone, Copy, Debug)]
 struct Node {
     idx: DeviceIndex,
@@ -107,6 +270,17 @@ impl Node {
 #[derive(Debug)]
 pub struct DeviceTree {
     devices: HashMap<DeviceIndex, AttachedDevice>,
+    /// The kernel devices in the order they were attached
+    order: Vec<DeviceIndex>,
+    /// Maps the device number of all `input` and `hidraw` devices to their
+    /// sysfs path, see [`DeviceTree::udev_device`]
+    #[cfg(feature = "udev")]
+    udev_cache: Option<HashMap<u64, PathBuf>>,
+    /// Skip virtual devices when scanning the system, see [`DeviceTree::skip_virtual`]
+    skip_virtual: bool,
+    /// The quirks loaded with [`DeviceTree::with_quirks`], most recent first.
+    /// Shared with our devices, which classify with them.
+    quirks: Arc<Vec<Quirk>>,
 }
 
 impl DeviceTree {
@@ -114,9 +288,61 @@ impl DeviceTree {
     pub fn new() -> Self {
         Self {
             devices: HashMap::new(),
+            order: Vec::new(),
+            #[cfg(feature = "udev")]
+            udev_cache: None,
+            skip_virtual: false,
+            quirks: Arc::default(),
         }
     }
 
+    /// Do not attach virtual devices, e.g. those created via `uinput`, when
+    /// scanning the system with [`DeviceTree::attach_system`],
+    /// [`DeviceTree::attach_proc_devices`] or a [`DeviceMonitor`].
+    /// See [`EvdevDevice::is_virtual`].
+    ///
+    /// Devices attached explicitly, e.g. with [`DeviceTree::attach_evdev`],
+    /// are always attached.
+    ///
+    /// ```no_run
+    /// use whodat::DeviceTree;
+    ///
+    /// let mut tree = DeviceTree::new().skip_virtual();
+    /// tree.attach_system().unwrap();
+    /// ```
+    pub fn skip_virtual(mut self) -> Self {
+        self.skip_virtual = true;
+        self
+    }
+
+    /// Load the quirk files in the given directory on top of the quirks
+    /// compiled into whodat, see [`Quirk::load_dir`] for the file format.
+    /// Quirks loaded this way take precedence over the compiled-in quirks
+    /// and over quirks loaded earlier.
+    ///
+    /// The quirks only apply to the devices attached to this tree afterwards,
+    /// other trees and devices created with the [`Builder`] only use the
+    /// compiled-in quirks. A directory that cannot be read or a malformed
+    /// quirk file is skipped with a warning.
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// use whodat::DeviceTree;
+    ///
+    /// let mut tree = DeviceTree::new().with_quirks(Path::new("/etc/whodat/quirks.d"));
+    /// tree.attach_system().unwrap();
+    /// ```
+    pub fn with_quirks(mut self, path: &Path) -> Self {
+        match Quirk::load_dir(path) {
+            Ok(mut quirks) => {
+                quirks.extend(self.quirks.iter().cloned());
+                self.quirks = Arc::new(quirks);
+            }
+            Err(e) => log::warn!("Unable to load quirks from {path:?}: {e}"),
+        }
+        self
+    }
+
     /// Attach a new evdev device from an open evdev file descriptor that can be
     /// `ioctl`'d for information. The returned [`DeviceIndex`] can be used to
     /// obtain the actual [`EvdevDevice`] later, see [`DeviceTree::get_device`]
@@ -125,10 +351,357 @@ impl DeviceTree {
     /// before calling [`DeviceTree::get_device`] to ensure the resulting device
     /// is built from the maximum information. Likewise, attaching more devices *may*
     /// change the information about an already attached device.
-    pub fn attach_evdev(&mut self, fd: OwnedFd) -> Result<DeviceIndex, Box<dyn Error>> {
-        let evdev = EvdevDevice::from_fd(fd)?;
-        let index = evdev.index();
-        let mut attached = AttachedDevice::Evdev(evdev);
+    ///
+    /// Returns [`Error::DeviceGone`] if the device was removed since the fd
+    /// was opened.
+    #[cfg(feature = "udev")]
+    pub fn attach_evdev(&mut self, fd: OwnedFd) -> Result<DeviceIndex, Error> {
+        let device = self.device_from_fd(fd, Some("input"))?;
+        Ok(self.attach(device))
+    }
+
+    /// Attach a new hidraw device from an open hidraw file descriptor that can be
+    /// `ioctl`'d for information. The returned [`DeviceIndex`] can be used to
+    /// obtain the actual [`HidrawDevice`] later, see [`DeviceTree::get_device`]
+    ///
+    /// A hidraw device shares its [`PhysicalDevice`] parent with any evdev
+    /// devices created by the same HID device, see [`DeviceTree::attach_evdev`].
+    #[cfg(feature = "udev")]
+    pub fn attach_hidraw(&mut self, fd: OwnedFd) -> Result<DeviceIndex, Error> {
+        let device = self.device_from_fd(fd, Some("hidraw"))?;
+        Ok(self.attach(device))
+    }
+
+    /// Attach all evdev and hidraw devices from the given open file
+    /// descriptors, see [`DeviceTree::attach_evdev`] and
+    /// [`DeviceTree::attach_hidraw`].
+    ///
+    /// If any device cannot be created, no device is attached and the error
+    /// is returned. Otherwise the returned indices are in the order of the
+    /// file descriptors.
+    #[cfg(feature = "udev")]
+    pub fn attach_many(
+        &mut self,
+        fds: Vec<OwnedFd>,
+    ) -> Result<Vec<DeviceIndex>, Error> {
+        let devices = fds
+            .into_iter()
+            .map(|fd| self.device_from_fd(fd, None))
+            .collect::<Result<Vec<AttachedDevice>, Error>>()?;
+
+        Ok(devices.into_iter().map(|d| self.attach(d)).collect())
+    }
+
+    /// Create the kernel device for the given fd, looking up its udev
+    /// device via [`DeviceTree::udev_device`]. If a subsystem is given, the
+    /// device must be in that subsystem.
+    #[cfg(feature = "udev")]
+    fn device_from_fd(
+        &mut self,
+        fd: OwnedFd,
+        subsystem: Option<&str>,
+    ) -> Result<AttachedDevice, Error> {
+        let f = File::from(fd);
+        let rdev = f.metadata()?.st_rdev();
+        let device = self.udev_device(rdev)?;
+        if subsystem.is_some() && device.subsystem().and_then(|s| s.to_str()) != subsystem {
+            return Err(Error::NotAnInputDevice(device.syspath().to_path_buf()));
+        }
+        Self::device_from_udev(f, &device)
+    }
+
+    /// Returns the udev device for the given device number.
+    ///
+    /// The device is looked up directly by its device number first, in the
+    /// `input` and then the `hidraw` subsystem. That lookup always reflects
+    /// the current state of udev and wins over the cache. Only where it
+    /// fails, e.g. where `/sys/dev/char` is not available, we fall back to
+    /// scanning the `input` and `hidraw` subsystems for the device number.
+    ///
+    /// A scan walks the whole subsystem and scanning once per attached
+    /// device makes attaching n devices O(n²). So the device numbers are
+    /// cached after the first scan and the udev device is then looked up by
+    /// its sysfs path: attaching 30 devices scans each subsystem once
+    /// instead of 30 times. The tradeoff is that the cache goes stale when
+    /// devices are added or removed between two attaches. A cache miss or a
+    /// cached sysfs path that no longer matches the device number causes a
+    /// rescan, but callers that track hotplug themselves should call
+    /// [`DeviceTree::invalidate_udev_cache`]. The [`DeviceMonitor`] does so
+    /// automatically.
+    #[cfg(feature = "udev")]
+    fn udev_device(&mut self, rdev: u64) -> Result<udev::Device, Error> {
+        for (subsystem, _) in UDEV_NODES {
+            if let Some(device) = util::udev_device_from_devnum(subsystem, rdev) {
+                return Ok(device);
+            }
+        }
+
+        let cached = self
+            .udev_cache
+            .as_ref()
+            .and_then(|cache| cache.get(&rdev))
+            .and_then(|syspath| udev::Device::from_syspath(syspath).ok())
+            .filter(|device| device.devnum() == Some(rdev));
+        if let Some(device) = cached {
+            return Ok(device);
+        }
+
+        let mut cache = HashMap::new();
+        for (subsystem, sysname) in UDEV_NODES {
+            cache.extend(util::udev_devnums(subsystem, sysname)?);
+        }
+        let syspath = cache.get(&rdev).cloned();
+        self.udev_cache = Some(cache);
+
+        // The device may have been removed since the fd was opened
+        let syspath = syspath.ok_or(Error::DeviceGone)?;
+        udev::Device::from_syspath(&syspath).map_err(|_| Error::DeviceGone)
+    }
+
+    /// Drop the cached udev information of this tree. The next attach that
+    /// cannot look up its device directly rescans udev, see
+    /// [`DeviceTree::udev_device`].
+    ///
+    /// A caller that monitors udev itself should call this whenever a
+    /// device is added or removed.
+    #[cfg(feature = "udev")]
+    pub fn invalidate_udev_cache(&mut self) {
+        self.udev_cache = None;
+    }
+
+    /// Create the kernel device for the opened device node and its udev device.
+    #[cfg(feature = "udev")]
+    fn device_from_udev(
+        f: File,
+        device: &udev::Device,
+    ) -> Result<AttachedDevice, Error> {
+        match device.subsystem().and_then(|s| s.to_str()) {
+            Some("input") => {
+                let source = evdev_device::KernelEvdev::from_udev(f, device)?;
+                Ok(AttachedDevice::Evdev(EvdevDevice::from_source(&source)))
+            }
+            Some("hidraw") => {
+                let hidraw = HidrawDevice::from_udev(f, device)?;
+                Ok(AttachedDevice::Hidraw(hidraw))
+            }
+            _ => Err(Error::NotAnInputDevice(device.syspath().to_path_buf())),
+        }
+    }
+
+    /// Create a [`DeviceMonitor`] that keeps a tree in sync with the devices
+    /// on the system, see [`DeviceMonitor::next_event`].
+    ///
+    /// Devices that already exist when the monitor is created are not
+    /// attached by the monitor, a caller should attach those first.
+    #[cfg(feature = "udev")]
+    pub fn monitor() -> Result<DeviceMonitor, Box<dyn StdError>> {
+        DeviceMonitor::new()
+    }
+
+    /// Attach all evdev and hidraw devices currently present on the system.
+    /// Devices that cannot be opened, e.g. due to missing permissions, are
+    /// skipped.
+    ///
+    /// Returns the indices of all attached devices.
+    #[cfg(feature = "udev")]
+    pub fn attach_system(&mut self) -> Result<Vec<DeviceIndex>, Error> {
+        let mut indices = Vec::new();
+        for subsystem in ["input", "hidraw"] {
+            let mut e = udev::Enumerator::new().map_err(Error::Udev)?;
+            e.match_subsystem(subsystem).map_err(Error::Udev)?;
+            for device in e.scan_devices().map_err(Error::Udev)? {
+                match self.attach_udev_device(&device) {
+                    Ok(Some(index)) => indices.push(index),
+                    Ok(None) => {}
+                    Err(e) => log::debug!("Skipping {:?}: {e}", device.syspath()),
+                }
+            }
+        }
+        Ok(indices)
+    }
+
+    /// Attach the kernel device for the given udev device by opening its
+    /// device node. Returns `None` for udev devices we do not handle, e.g.
+    /// the `inputN` devices without a device node.
+    #[cfg(feature = "udev")]
+    pub(crate) fn attach_udev_device(
+        &mut self,
+        device: &udev::Device,
+    ) -> Result<Option<DeviceIndex>, Error> {
+        let devnode = match device.devnode() {
+            Some(devnode) => devnode,
+            None => return Ok(None),
+        };
+        let subsystem = device.subsystem().and_then(|s| s.to_str());
+        let sysname = device.sysname().to_str().unwrap_or("");
+
+        match subsystem {
+            Some("input") if sysname.starts_with("event") => {}
+            Some("hidraw") => {}
+            _ => return Ok(None),
+        };
+        if self.skip_virtual && util::is_virtual(device.syspath()) {
+            return Ok(None);
+        }
+
+        // We already have the udev device, no need to scan udev again
+        let f = File::open(devnode)?;
+        let device = Self::device_from_udev(f, device)?;
+        Ok(Some(self.attach(device)))
+    }
+
+    /// Attach the evdev or hidraw device for the given udev device, e.g. one
+    /// from the caller's own udev enumeration or monitor. Unlike
+    /// [`DeviceTree::attach_evdev`] the device node is not opened, the device
+    /// is classified from sysfs and its udev properties only. This works
+    /// where the device node is not accessible but gives less information
+    /// than an fd, e.g. an evdev device's [`EvdevDevice::abs_resolution`] is
+    /// not available.
+    ///
+    /// Returns [`Error::NotAnInputDevice`] if the device is neither an
+    /// `eventN` nor a `hidrawN` device.
+    #[cfg(feature = "udev")]
+    pub fn attach_udev(&mut self, device: udev::Device) -> Result<DeviceIndex, Error> {
+        let subsystem = device.subsystem().and_then(|s| s.to_str());
+        let sysname = device.sysname().to_str().unwrap_or("");
+        let device = match subsystem {
+            Some("input") if sysname.starts_with("event") => {
+                AttachedDevice::Evdev(EvdevDevice::from_syspath(device.syspath())?)
+            }
+            Some("hidraw") => AttachedDevice::Hidraw(HidrawDevice::from_udev_sysfs(&device)?),
+            _ => return Err(Error::NotAnInputDevice(device.syspath().to_path_buf())),
+        };
+        Ok(self.attach(device))
+    }
+
+    /// Returns the index of the kernel device with the given device node,
+    /// e.g. `/dev/input/event7`, if any such device is attached to this tree.
+    pub fn find_by_devnode(&self, path: &Path) -> Option<DeviceIndex> {
+        self.devices.iter().find_map(|(idx, d)| match d {
+            AttachedDevice::Evdev(evdev) if evdev.devnode().as_deref() == Some(path) => {
+                Some(*idx)
+            }
+            AttachedDevice::Hidraw(hidraw) if hidraw.devnode().as_deref() == Some(path) => {
+                Some(*idx)
+            }
+            _ => None,
+        })
+    }
+
+    /// Returns the index of the kernel device with the given sysfs path,
+    /// e.g. `/sys/devices/.../input/input7/event7`, if any such device is
+    /// attached to this tree.
+    pub fn find_by_syspath(&self, path: &Path) -> Option<DeviceIndex> {
+        self.devices.iter().find_map(|(idx, d)| match d {
+            AttachedDevice::Evdev(evdev) if evdev.sysfs_path().as_deref() == Some(path) => {
+                Some(*idx)
+            }
+            AttachedDevice::Hidraw(hidraw) if hidraw.sysfs_path() == path => Some(*idx),
+            _ => None,
+        })
+    }
+
+    /// Remove the device from the tree and return it, or `None` if no device
+    /// with this index exists. Where the device is a kernel device and its
+    /// parent has no other children, the parent is removed too. Where the
+    /// device is a [`PhysicalDevice`], all its children are removed.
+    ///
+    /// Use [`HasParent::parent`] on the returned device and
+    /// [`DeviceTree::get_parent_device`] to check whether the parent was removed.
+    ///
+    /// A [`DeviceIndex`] is never reused, so the index of a removed device
+    /// never refers to a different device later.
+    pub fn remove(&mut self, idx: &DeviceIndex) -> Option<AttachedDevice> {
+        self.detach(idx).into_iter().next()
+    }
+
+    /// Remove the device from the tree, see [`DeviceTree::remove`].
+    ///
+    /// Returns the indices of all removed devices.
+    pub(crate) fn remove_device(&mut self, idx: &DeviceIndex) -> Vec<DeviceIndex> {
+        self.detach(idx).iter().map(|d| d.index()).collect()
+    }
+
+    /// Remove the device and any children or parent that go with it, see
+    /// [`DeviceTree::remove`]. Returns all removed devices, the requested
+    /// device first.
+    fn detach(&mut self, idx: &DeviceIndex) -> Vec<AttachedDevice> {
+        let device = match self.devices.remove(idx) {
+            Some(device) => device,
+            None => return Vec::new(),
+        };
+        self.order.retain(|i| i != idx);
+
+        let pidx = match &device {
+            AttachedDevice::Evdev(evdev) => evdev.try_parent(),
+            AttachedDevice::Hidraw(hidraw) => hidraw.try_parent(),
+            AttachedDevice::Parent(parent) => {
+                let children: Vec<DeviceIndex> = parent.iter().copied().collect();
+                let mut removed = vec![device];
+                for child in children {
+                    if let Some(child_device) = self.devices.remove(&child) {
+                        self.order.retain(|i| *i != child);
+                        removed.push(child_device);
+                    }
+                }
+                return removed;
+            }
+        };
+
+        let mut removed = vec![device];
+        if let Some(pidx) = pidx {
+            if let Some(AttachedDevice::Parent(mut parent)) = self.devices.remove(&pidx) {
+                parent.remove_child(idx);
+                if parent.iter().next().is_none() {
+                    removed.push(AttachedDevice::Parent(parent));
+                } else {
+                    // Drop whatever the removed child contributed
+                    let children: Vec<DeviceIndex> = parent.iter().copied().collect();
+                    parent.recalculate(children.iter().filter_map(|c| self.devices.get(c)));
+                    self.devices.insert(pidx, AttachedDevice::Parent(parent));
+                }
+            }
+        }
+
+        removed
+    }
+
+    /// Attach all input devices listed in `/proc/bus/input/devices`, see
+    /// [`ProcInputDevice`]. Unlike [`DeviceTree::attach_system`] this does
+    /// not require access to the device nodes.
+    ///
+    /// Returns the indices of all attached devices.
+    pub fn attach_proc_devices(&mut self) -> Result<Vec<DeviceIndex>, Error> {
+        let devices = ProcInputDevice::read()?;
+        Ok(devices
+            .iter()
+            .map(|d| EvdevDevice::from_source(d))
+            .filter(|d| !(self.skip_virtual && d.is_virtual()))
+            .map(|d| self.attach_evdev_device(d))
+            .collect())
+    }
+
+    /// Attach an already created [`EvdevDevice`], e.g. one created with
+    /// [`EvdevDevice::from_path`] or the [`Builder`], see [`DeviceTree::attach_evdev`].
+    ///
+    /// A clone of a device that is already in this tree is attached as a new
+    /// device with a new [`DeviceIndex`].
+    pub fn attach_evdev_device(&mut self, device: EvdevDevice) -> DeviceIndex {
+        self.attach(AttachedDevice::Evdev(device))
+    }
+
+    /// Insert the kernel device into the tree and hook it up to an existing
+    /// or new [`PhysicalDevice`] parent.
+    fn attach(&mut self, mut attached: AttachedDevice) -> DeviceIndex {
+        // A clone of a device already in the tree is a new device, it must
+        // not replace the original
+        if self.devices.contains_key(&attached.index()) {
+            attached.set_node(Node::new());
+        }
+        let index = attached.index();
+        if let AttachedDevice::Evdev(ref mut device) = attached {
+            device.set_quirks(Arc::clone(&self.quirks));
+        }
 
         let parent: Option<&mut PhysicalDevice> = self.devices.values_mut().find_map(|d| match d {
             AttachedDevice::Parent(parent) => {
@@ -141,36 +714,66 @@ impl DeviceTree {
             _ => None,
         });
 
-        match parent {
+        let pindex = match parent {
             Some(parent) => {
                 parent.add_child(&attached);
-                attached.set_parent(&parent);
+                parent.index()
             }
             None => {
                 let mut parent = PhysicalDevice::new();
+                parent.set_quirks(Arc::clone(&self.quirks));
                 let pindex = parent.index();
                 parent.add_child(&attached);
-                attached.set_parent(&parent);
                 self.devices
                     .insert(pindex.clone(), AttachedDevice::Parent(parent));
+                pindex
             }
-        }
+        };
 
+        // Link the child before it becomes visible in the tree, so
+        // HasParent::parent() is valid as soon as the index is returned.
+        attached.set_parent(pindex);
         self.devices.insert(index.clone(), attached);
+        self.order.push(index);
 
         //println!("Hashmap is {:?}", self.devices);
 
-        Ok(index)
+        index
     }
 
     /// Given the [`DeviceIndex`] returned by [`DeviceTree::attach_evdev`] return
     /// that device. This is the generic version that returns the device
     /// and punts device type detection to the caller. For more specific versions
     /// see [`DeviceTree::get_evdev_device`] and [`DeviceTree::get_parent_device`].
+    ///
+    /// The device is borrowed from the tree, nothing is cloned. Matching on
+    /// the returned reference binds references to the device:
+    ///
+    /// ```
+    /// use whodat::{AttachedDevice, Builder, DeviceTree};
+    ///
+    /// let mut tree = DeviceTree::new();
+    /// let idx = tree.attach_evdev_device(Builder::new().name("Generic Mouse").build().unwrap());
+    /// match tree.get_device(&idx) {
+    ///     // device is a &EvdevDevice
+    ///     Some(AttachedDevice::Evdev(device)) => println!("{}", device.name()),
+    ///     _ => unreachable!(),
+    /// }
+    /// ```
     pub fn get_device(&self, idx: &DeviceIndex) -> Option<&AttachedDevice> {
         self.devices.get(idx).and_then(|x| Some(x))
     }
 
+    /// The mutable version of [`DeviceTree::get_device`], for updating a
+    /// device in place.
+    ///
+    /// The device remains in the tree and keeps its [`DeviceIndex`] and
+    /// parent. A caller must not replace the device with a different
+    /// device, use [`DeviceTree::remove`] and attach the new device instead.
+    pub fn get_device_mut(&mut self, idx: &DeviceIndex) -> Option<&mut AttachedDevice> {
+        self.devices.get_mut(idx)
+    }
+
     /// Given the [`DeviceIndex`] returned by [`DeviceTree::attach_evdev`] return
     /// that device if it is indeed an [`EvdevDevice`].
     pub fn get_evdev_device(&self, idx: &DeviceIndex) -> Option<&EvdevDevice> {
@@ -181,6 +784,16 @@ impl DeviceTree {
         }
     }
 
+    /// Given the [`DeviceIndex`] returned by [`DeviceTree::attach_hidraw`] return
+    /// that device if it is indeed a [`HidrawDevice`].
+    pub fn get_hidraw_device(&self, idx: &DeviceIndex) -> Option<&HidrawDevice> {
+        let d = self.devices.get(idx)?;
+        match &d {
+            AttachedDevice::Hidraw(hidraw) => Some(&hidraw),
+            _ => None,
+        }
+    }
+
     /// Given the [`DeviceIndex`] returned by [`DeviceTree::attach_evdev`] return
     /// that device if it is indeed a [`PhysicalDevice`].
     pub fn get_parent_device(&self, idx: &DeviceIndex) -> Option<&PhysicalDevice> {
@@ -191,10 +804,109 @@ impl DeviceTree {
         }
     }
 
+    /// Serialize the whole tree into a versioned JSON format, see
+    /// [`DeviceTree::deserialize`]. Unlike [`EvdevDevice::serialize`] this
+    /// includes the attachment of each device, i.e. its [`DeviceIndex`] and
+    /// its parent.
+    pub fn serialize(&self) -> String {
+        // Each parent goes before its first child so the order of
+        // children is restored on deserialization
+        let entry = |idx: &DeviceIndex, parent: Option<DeviceIndex>| {
+            self.devices.get(idx).map(|device| serialize::TreeEntry {
+                index: *idx,
+                parent,
+                device,
+            })
+        };
+
+        let mut entries: Vec<serialize::TreeEntry<&AttachedDevice>> = Vec::new();
+        let mut parents: Vec<DeviceIndex> = Vec::new();
+        for idx in self.order.iter() {
+            let pidx = match self.devices.get(idx) {
+                Some(AttachedDevice::Evdev(evdev)) => evdev.try_parent(),
+                Some(AttachedDevice::Hidraw(hidraw)) => hidraw.try_parent(),
+                _ => continue,
+            };
+            if let Some(pidx) = pidx.filter(|p| !parents.contains(p)) {
+                parents.push(pidx);
+                entries.extend(entry(&pidx, None));
+            }
+            entries.extend(entry(idx, pidx));
+        }
+        serialize::to_string(&entries)
+    }
+
+    /// Reconstruct a tree from the data returned by [`DeviceTree::serialize`].
+    /// Data serialized with a different [`FORMAT_VERSION`] is rejected.
+    ///
+    /// All devices keep the [`DeviceIndex`] they had in the serialized tree.
+    pub fn deserialize(data: &str) -> Result<DeviceTree, Box<dyn StdError>> {
+        let entries: Vec<serialize::TreeEntry<AttachedDevice>> = serialize::from_str(data)?;
+        let mut tree = DeviceTree::new();
+        for entry in entries {
+            // Make sure we never hand out an index of a restored device
+            NEXT_ID.fetch_max(entry.index.idx + 1, Ordering::Relaxed);
+
+            let mut device = entry.device;
+            device.set_node(Node {
+                idx: entry.index,
+                parent: entry.parent,
+            });
+            match (&device, entry.parent) {
+                (AttachedDevice::Parent(_), _) => {}
+                (_, Some(pidx)) => {
+                    match tree.devices.get_mut(&pidx) {
+                        Some(AttachedDevice::Parent(parent)) => parent.restore_child(entry.index),
+                        _ => return Err(format!("Missing parent for {:?}", entry.index).into()),
+                    }
+                    tree.order.push(entry.index);
+                }
+                (_, None) => return Err(format!("No parent for {:?}", entry.index).into()),
+            }
+            tree.devices.insert(entry.index, device);
+        }
+        Ok(tree)
+    }
+
     /// Returns an iterator over all [`AttachedDevice`]s that are part of this tree.
+    /// The devices are borrowed from the tree, nothing is cloned.
     pub fn iter(&self) -> impl Iterator<Item=&AttachedDevice> + '_ {
         self.devices.values()
     }
+
+    /// Returns an iterator over the children of the given [`PhysicalDevice`]
+    /// in the order they were attached.
+    ///
+    /// This is a shortcut for [`PhysicalDevice::iter`] and
+    /// [`DeviceTree::get_device`]:
+    ///
+    /// ```
+    /// use whodat::{Builder, DeviceTree, HasParent};
+    ///
+    /// let mut tree = DeviceTree::new();
+    /// let idx = tree.attach_evdev_device(Builder::new().name("Generic Mouse").build().unwrap());
+    /// let pidx = tree.get_evdev_device(&idx).unwrap().parent();
+    /// let parent = tree.get_parent_device(&pidx).unwrap();
+    /// let names: Vec<&str> = tree
+    ///     .children(parent)
+    ///     .filter_map(|d| d.as_evdev())
+    ///     .map(|d| d.name())
+    ///     .collect();
+    /// assert_eq!(names, ["Generic Mouse"]);
+    /// ```
+    pub fn children<'a>(
+        &'a self,
+        parent: &'a PhysicalDevice,
+    ) -> impl Iterator<Item=&'a AttachedDevice> + 'a {
+        parent.iter().filter_map(|idx| self.devices.get(idx))
+    }
+
+    /// Returns an iterator over all kernel devices (i.e. all [`AttachedDevice`]s
+    /// except the [`PhysicalDevice`]s) that are part of this tree, in the order
+    /// they were attached.
+    pub fn iter_devices(&self) -> impl Iterator<Item=&AttachedDevice> + '_ {
+        self.order.iter().filter_map(|idx| self.devices.get(idx))
+    }
 }
 
 /// The [`HasParent`] trait is implemented by devices that have a single parent
@@ -203,10 +915,214 @@ impl DeviceTree {
 pub trait HasParent {
     /// Return the parent [`DeviceIndex`] of this kernel device - use
     /// with [`DeviceTree::get_device`] to fetch the parent device.
-    fn parent(&self) -> DeviceIndex;
+    ///
+    /// The parent is set by the time the device's [`DeviceIndex`] is returned
+    /// from [`DeviceTree::attach_evdev`] or [`DeviceTree::attach_hidraw`],
+    /// regardless of any other devices in the tree.
+    ///
+    /// # Panics
+    ///
+    /// If the device is not attached to a [`DeviceTree`], see
+    /// [`HasParent::try_parent`] for a non-panicking version.
+    fn parent(&self) -> DeviceIndex {
+        match self.try_parent() {
+            Some(index) => index,
+            None => panic!("Device is not attached to a DeviceTree"),
+        }
+    }
+
+    /// Return the parent [`DeviceIndex`] of this kernel device or `None`
+    /// if the device is not (yet) attached to a [`DeviceTree`].
+    fn try_parent(&self) -> Option<DeviceIndex>;
 }
 
 pub trait HasCapability {
     /// Return the set of capabilities of this device.
     fn capabilities(&self) -> Vec<Capability>;
+
+    /// Returns `true` if this device has the given capability.
+    ///
+    /// ```
+    /// use whodat::{Builder, Capability, HasCapability};
+    ///
+    /// let device = Builder::new().name("SynPS/2 Synaptics TouchPad").build().unwrap();
+    /// if device.has(Capability::Touchpad) {
+    ///     println!("This is a touchpad");
+    /// }
+    /// ```
+    fn has(&self, cap: Capability) -> bool {
+        self.capabilities().contains(&cap)
+    }
+
+    /// Returns `Some(true)` if this device has the given capability,
+    /// `Some(false)` if it does not or `None` if there is insufficient
+    /// information about this device to tell either way.
+    ///
+    /// The default implementation returns `None` for a device without any
+    /// capabilities.
+    fn has_capability(&self, cap: Capability) -> Option<bool> {
+        let capabilities = self.capabilities();
+        if capabilities.is_empty() {
+            None
+        } else {
+            Some(capabilities.contains(&cap))
+        }
+    }
+}
+
+/// The information common to all devices, for code that handles kernel
+/// devices and [`PhysicalDevice`]s alike. This trait is object-safe:
+///
+/// ```
+/// use whodat::{Builder, Device, HasCapability, PhysicalDevice};
+///
+/// let devices: Vec<Box<dyn Device>> = vec![
+///     Box::new(Builder::new().name("Generic Mouse").build().unwrap()),
+///     Box::new(PhysicalDevice::new()),
+/// ];
+/// for device in devices.iter() {
+///     println!("{}: {:?}", device.name(), device.capabilities());
+/// }
+/// ```
+///
+/// For a device in a [`DeviceTree`] see [`AttachedDevice::as_device`].
+pub trait Device: HasCapability {
+    /// The name of this device, see e.g. [`EvdevDevice::name`] and
+    /// [`PhysicalDevice::name`]
+    fn name(&self) -> &str;
+
+    /// The index of the [`PhysicalDevice`] this device belongs to, or `None`
+    /// for a [`PhysicalDevice`] or a device that is not attached to a
+    /// [`DeviceTree`], see [`HasParent::try_parent`].
+    fn physical_parent(&self) -> Option<DeviceIndex>;
+}
+
+impl HasCapability for AttachedDevice {
+    fn capabilities(&self) -> Vec<Capability> {
+        self.as_device().capabilities()
+    }
+
+    fn has_capability(&self, cap: Capability) -> Option<bool> {
+        self.as_device().has_capability(cap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parent_set_on_attach() {
+        let mut tree = DeviceTree::new();
+        let mouse = EvdevDevice::from_parts(
+            "Logitech USB Optical Mouse",
+            0x046d,
+            0xc077,
+            &["ID_INPUT_MOUSE"],
+            &[],
+            None,
+        );
+        let keyboard = EvdevDevice::from_parts(
+            "Dell KB216 Wired Keyboard",
+            0x413c,
+            0x2113,
+            &["ID_INPUT_KEY", "ID_INPUT_KEYBOARD"],
+            &[],
+            None,
+        );
+        let midx = tree.attach_evdev_device(mouse);
+        // The parent is valid before anything else happens to the tree
+        let mparent = tree.get_evdev_device(&midx).unwrap().parent();
+        assert!(tree.get_parent_device(&mparent).is_some());
+
+        let kidx = tree.attach_evdev_device(keyboard);
+        let kparent = tree.get_evdev_device(&kidx).unwrap().parent();
+        assert!(tree.get_parent_device(&kparent).is_some());
+        assert_ne!(mparent, kparent);
+        assert_eq!(tree.get_evdev_device(&midx).unwrap().parent(), mparent);
+    }
+
+    /// All event nodes on this system, sorted by name
+    #[cfg(feature = "udev")]
+    fn event_nodes() -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = match std::fs::read_dir("/dev/input") {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| {
+                    path.file_name()
+                        .and_then(|n| n.to_str())
+                        .map_or(false, |n| n.starts_with("event"))
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    #[cfg(feature = "udev")]
+    #[ignore = "needs read access to an event node"]
+    fn test_attach_event_node() {
+        let (path, f) = event_nodes()
+            .into_iter()
+            .find_map(|path| File::open(&path).ok().map(|f| (path, f)))
+            .expect("No readable event node");
+        let mut tree = DeviceTree::new();
+        let rdev = f.metadata().unwrap().st_rdev();
+        let device = tree.udev_device(rdev).unwrap();
+        assert_eq!(device.devnode(), Some(path.as_path()));
+
+        let idx = tree.attach_evdev(OwnedFd::from(f)).unwrap();
+        let device = tree.get_evdev_device(&idx).unwrap();
+        assert_eq!(device.devnode().as_deref(), Some(path.as_path()));
+        assert!(device.sysfs_path().is_some());
+    }
+
+    /// Compares the direct lookup by device number with scanning udev per
+    /// device and with the cached scan, for up to 30 event nodes. Run with
+    /// `cargo test -- --ignored --nocapture bench_udev_device`.
+    #[test]
+    #[cfg(feature = "udev")]
+    #[ignore = "benchmark, needs event nodes"]
+    fn bench_udev_device() {
+        use std::time::Instant;
+
+        // stat() doesn't need read access to the node
+        let rdevs: Vec<u64> = event_nodes()
+            .iter()
+            .filter_map(|path| std::fs::metadata(path).ok())
+            .map(|m| m.st_rdev())
+            .take(30)
+            .collect();
+        assert!(!rdevs.is_empty(), "No event nodes");
+
+        let start = Instant::now();
+        for rdev in &rdevs {
+            util::udev_device_from_devnum("input", *rdev).unwrap();
+        }
+        let direct = start.elapsed();
+
+        let start = Instant::now();
+        for rdev in &rdevs {
+            let devnums = util::udev_devnums("input", "event*").unwrap();
+            assert!(devnums.iter().any(|(n, _)| n == rdev));
+        }
+        let scanned = start.elapsed();
+
+        let start = Instant::now();
+        let devnums: HashMap<u64, PathBuf> = util::udev_devnums("input", "event*")
+            .unwrap()
+            .into_iter()
+            .collect();
+        for rdev in &rdevs {
+            udev::Device::from_syspath(&devnums[rdev]).unwrap();
+        }
+        let cached = start.elapsed();
+
+        println!(
+            "{} devices: direct {direct:?}, scan per device {scanned:?}, cached scan {cached:?}",
+            rdevs.len()
+        );
+    }
 }
@@ -0,0 +1,92 @@
+use crate::*;
+
+use std::collections::VecDeque;
+use std::os::fd::{AsRawFd, RawFd};
+
+/// An event emitted by the [`DeviceMonitor`], see [`DeviceMonitor::next_event`].
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug)]
+pub enum MonitorEvent {
+    /// A kernel device was attached to the [`DeviceTree`].
+    Added(DeviceIndex),
+    /// A device was removed from the [`DeviceTree`], the [`DeviceIndex`] is no
+    /// longer valid. This event is also emitted for a [`PhysicalDevice`] that
+    /// was removed because it lost its last child.
+    Removed(DeviceIndex),
+}
+
+/// The [`DeviceMonitor`] keeps a [`DeviceTree`] in sync with the `input` and
+/// `hidraw` devices on the system, see [`DeviceTree::monitor`].
+///
+/// The monitor never blocks. A caller is expected to poll the monitor's
+/// file descriptor in its own event loop and call
+/// [`DeviceMonitor::next_event`] whenever that fd becomes readable.
+pub struct DeviceMonitor {
+    socket: udev::MonitorSocket,
+    pending: VecDeque<MonitorEvent>,
+}
+
+impl DeviceMonitor {
+    pub(crate) fn new() -> Result<DeviceMonitor, Box<dyn StdError>> {
+        let socket = udev::MonitorBuilder::new()?
+            .match_subsystem("input")?
+            .match_subsystem("hidraw")?
+            .listen()?;
+
+        Ok(DeviceMonitor {
+            socket,
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Process the pending udev events and update the given tree accordingly.
+    ///
+    /// Newly added devices are attached to the tree, removed devices are
+    /// removed from the tree together with their parent if that parent has
+    /// no children left.
+    /// Like [`DeviceTree::attach_system`], a new device that cannot be
+    /// attached, e.g. because its device node is not accessible, is skipped.
+    ///
+    /// Returns the next [`MonitorEvent`] or `None` if no events are pending.
+    pub fn next_event(
+        &mut self,
+        tree: &mut DeviceTree,
+    ) -> Result<Option<MonitorEvent>, Box<dyn StdError>> {
+        while self.pending.is_empty() {
+            let event = match self.socket.next() {
+                Some(event) => event,
+                None => break,
+            };
+            let device = event.device();
+            match event.event_type() {
+                udev::EventType::Add => {
+                    tree.invalidate_udev_cache();
+                    // A node we cannot open (e.g. a root-only hidraw node) or
+                    // that is already gone again is not an error for the caller
+                    match tree.attach_udev_device(&device) {
+                        Ok(Some(idx)) => self.pending.push_back(MonitorEvent::Added(idx)),
+                        Ok(None) => {}
+                        Err(e) => log::debug!("Skipping {:?}: {e}", device.syspath()),
+                    }
+                }
+                udev::EventType::Remove => {
+                    tree.invalidate_udev_cache();
+                    if let Some(idx) = tree.find_by_syspath(device.syspath()) {
+                        for removed in tree.remove_device(&idx) {
+                            self.pending.push_back(MonitorEvent::Removed(removed));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(self.pending.pop_front())
+    }
+}
+
+impl AsRawFd for DeviceMonitor {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.as_raw_fd()
+    }
+}
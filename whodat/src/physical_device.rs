@@ -1,8 +1,52 @@
 use crate::*;
 
-use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::path::PathBuf;
 
+/// The capabilities that decide a device's abstract type, in order of priority.
+/// The first capability the device has decides the abstract type, regardless
+/// of the order the capabilities were added in.
+///
+/// The very specific capabilities come first: a tablet with a touchscreen
+/// (e.g. a Wacom Cintiq) is a tablet and a gamepad with a touchpad (e.g. a
+/// DualSense) is a gaming device. A keyboard with a generic pointer is a
+/// keyboard but a keyboard with a touchpad is a pointer. A keyboard with
+/// a pointingstick (e.g. a ThinkPad keyboard) is a keyboard, see
+/// [`PhysicalDevice::calculate_abstract_type`].
+///
+/// udev tags any device with keys as [`Capability::Keyboard`], so a device
+/// that only has media or consumer control keys (e.g. a volume knob or a
+/// remote) is a keyboard too. A lot of keyboard-like devices also have a
+/// switch (e.g. the ThinkPad Extra Buttons with `SW_TABLET_MODE`), so we only
+/// use the switch type for something that's *just* a switch, e.g. a lid
+/// switch. A device without any of these capabilities has no abstract type.
+const ABSTRACT_TYPE_PRIORITY: &[(Capability, AbstractType)] = &[
+    (Capability::Tablet, AbstractType::Tablet),
+    (Capability::TabletScreen, AbstractType::Tablet),
+    (Capability::TabletExternal, AbstractType::Tablet),
+    (Capability::TabletPad, AbstractType::Tablet),
+    (Capability::Touchscreen, AbstractType::Touchscreen),
+    (Capability::Stylus, AbstractType::Tablet),
+    (Capability::Gamepad, AbstractType::GamingDevice),
+    (Capability::Joystick, AbstractType::GamingDevice),
+    (Capability::RacingWheel, AbstractType::GamingDevice),
+    (Capability::Pointingstick, AbstractType::Pointer),
+    (Capability::Touchpad, AbstractType::Pointer),
+    (Capability::Clickpad, AbstractType::Pointer),
+    (Capability::Pressurepad, AbstractType::Pointer),
+    (Capability::Trackball, AbstractType::Pointer),
+    // Dials usually have a button, so udev tags them as keyboards too.
+    // Scroll-only devices are dials, see EvdevDevice::is_scroll_only
+    (Capability::Dial, AbstractType::Dial),
+    (Capability::Keyboard, AbstractType::Keyboard),
+    (Capability::Pointer, AbstractType::Pointer),
+    (Capability::Switch, AbstractType::Switch),
+    // Lowest priority: a controller's accelerometer node yields to GamingDevice,
+    // a standalone one is a Sensor
+    (Capability::Accelerometer, AbstractType::Sensor),
+];
+
 /// The [`PhysicalDevice`] struct represents the device and the queryable
 /// information about this (physical) device.
 ///
@@ -11,41 +55,205 @@ use std::path::PathBuf;
 /// the controller which itself has subdevices for the gaming features and
 /// the touchpad (and possibly others). For a Wacom Intuos Pro series tablet
 /// this is a tablet, even though that tablet also has a touchscreen.
-#[derive(Debug)]
+///
+/// A clone is a snapshot of the device at the time of cloning, it is not
+/// updated as children are added to or removed from the [`DeviceTree`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PhysicalDevice {
     /// Attachment in the [`DeviceTree`]
+    #[serde(skip, default = "Node::new")]
     node: Node,
+    #[serde(default)]
+    name: String,
+    /// True if the name is from udev and must not be replaced by our
+    /// children's names
+    #[serde(skip)]
+    name_from_udev: bool,
     abstract_types: Vec<AbstractType>,
-    caps: HashSet<Capability>,
+    /// Sorted so the serialized device is reproducible
+    caps: BTreeSet<Capability>,
+    sources: HashSet<Source>,
+    /// The most reliable source of each of our capabilities, see
+    /// [`PhysicalDevice::capability_confidence`]
+    #[serde(skip)]
+    capability_sources: HashMap<Capability, Source>,
+    #[serde(skip)]
     children: Vec<DeviceIndex>,
+    /// The name and capabilities of our evdev children, in the order they
+    /// were added, see [`PhysicalDevice::primary_for`]
+    #[serde(skip)]
+    evdev_children: Vec<(DeviceIndex, String, Vec<Capability>)>,
+    /// The abstract type of a well-known device, overriding our own
+    #[serde(skip)]
+    quirk_type: Option<AbstractType>,
     sysfs: Option<PathBuf>,
+    /// The USB device our anchor belongs to, if any
+    #[serde(skip)]
+    usb_device: Option<PathBuf>,
+    /// The physical path of our first evdev child without the
+    /// interface-specific suffix, see [`phys_prefix`]
+    #[serde(skip)]
+    phys: Option<String>,
+    /// The udev `ID_PATH` of our first evdev child, used to group children
+    /// where we have no anchor in sysfs
+    #[serde(skip)]
+    id_path: Option<String>,
+    /// The quirks loaded at runtime for our tree, see [`DeviceTree::with_quirks`]
+    #[serde(skip)]
+    quirks: Arc<Vec<Quirk>>,
 }
 
 impl PhysicalDevice {
     pub fn new() -> Self {
         Self {
             node: Node::new(),
+            name: String::new(),
+            name_from_udev: false,
             abstract_types: Vec::new(),
-            caps: HashSet::new(),
+            caps: BTreeSet::new(),
+            sources: HashSet::new(),
+            capability_sources: HashMap::new(),
             children: Vec::new(),
+            evdev_children: Vec::new(),
+            quirk_type: None,
             sysfs: None,
+            usb_device: None,
+            phys: None,
+            id_path: None,
+            quirks: Arc::default(),
         }
     }
 
+    /// Use the given quirks in addition to the compiled-in ones for the
+    /// children added afterwards, see [`DeviceTree::with_quirks`].
+    pub(crate) fn set_quirks(&mut self, quirks: Arc<Vec<Quirk>>) {
+        self.quirks = quirks;
+    }
+
+    /// Serialize this device into a versioned JSON format, see
+    /// [`PhysicalDevice::deserialize`]. The serialized device does not include
+    /// its attachment to a [`DeviceTree`] or its children.
+    pub fn serialize(&self) -> String {
+        serialize::to_string(self)
+    }
+
+    /// Reconstruct a device from the data returned by [`PhysicalDevice::serialize`].
+    /// Data serialized with a different [`FORMAT_VERSION`] is rejected.
+    ///
+    /// The returned device is not attached to any [`DeviceTree`].
+    pub fn deserialize(data: &str) -> Result<PhysicalDevice, Box<dyn StdError>> {
+        serialize::from_str(data)
+    }
+
     /// Return true if the given other device is a child of this device or false otherwise
+    ///
+    /// A device is our child if it is below our anchor in sysfs (see
+    /// [`PhysicalDevice::set_syspath`]) or if it hangs off the same USB device
+    /// as our anchor. The latter groups composite devices whose kernel devices
+    /// are spread across multiple USB interfaces.
+    ///
+    /// Where we have no anchor in sysfs, e.g. for a device replayed from a
+    /// [`Capture`], an evdev device is our child if it has the same physical
+    /// path as our evdev children, see [`EvdevDevice::phys`] and
+    /// [`phys_prefix`]. Likewise, e.g. because no ancestor of our first child
+    /// is in the `input` subsystem, an evdev device with the same udev
+    /// `ID_PATH` is our child too.
     pub(crate) fn match_device(&mut self, other: &AttachedDevice) -> bool {
-        if self.sysfs.is_none() {
-            return false;
+        if self.matches_sysfs(other) {
+            log::trace!("{}: matched {:?} via sysfs", self.name, self.sysfs);
+            return true;
         }
-        match other {
-            AttachedDevice::Evdev(evdev) => {
-                evdev.sysfs_path().starts_with(self.sysfs.as_ref().unwrap())
+        let evdev = match other {
+            AttachedDevice::Evdev(evdev) => evdev,
+            _ => return false,
+        };
+        if let (None, Some(id_path)) = (&self.sysfs, &self.id_path) {
+            if evdev.udev_property("ID_PATH").as_ref() == Some(id_path) {
+                log::trace!(
+                    "{}: matched {} via ID_PATH {id_path}",
+                    self.name,
+                    evdev.name()
+                );
+                return true;
+            }
+        }
+        let matched = match (&self.sysfs, &self.phys) {
+            (None, Some(phys)) => {
+                evdev.phys().as_deref().and_then(phys_prefix) == Some(phys.as_str())
             }
             _ => false,
+        };
+        if matched {
+            log::trace!(
+                "{}: matched {} via phys {:?}",
+                self.name,
+                evdev.name(),
+                self.phys
+            );
+        }
+        matched
+    }
+
+    fn matches_sysfs(&self, other: &AttachedDevice) -> bool {
+        let anchor = match &self.sysfs {
+            Some(anchor) => anchor,
+            None => return false,
+        };
+        let syspath = match other {
+            AttachedDevice::Evdev(evdev) => match evdev.sysfs_path() {
+                Some(path) => path,
+                None => return false,
+            },
+            AttachedDevice::Hidraw(hidraw) => hidraw.sysfs_path(),
+            _ => return false,
+        };
+        if syspath.starts_with(anchor) {
+            return true;
+        }
+
+        self.shares_usb_device(syspath)
+    }
+
+    /// Without udev we don't know the USB device
+    #[cfg(not(feature = "udev"))]
+    fn shares_usb_device(&self, _syspath: &Path) -> bool {
+        false
+    }
+
+    /// Return true if the device at the syspath hangs off our USB device
+    #[cfg(feature = "udev")]
+    fn shares_usb_device(&self, syspath: &Path) -> bool {
+        let usb_device = match &self.usb_device {
+            Some(usb_device) => usb_device,
+            None => return false,
+        };
+        let mut device = match udev::Device::from_syspath(syspath) {
+            Ok(device) => device,
+            Err(_) => return false,
+        };
+        while let Some(parent) = device.parent() {
+            if parent.syspath() == usb_device {
+                return true;
+            }
+            device = parent;
         }
+        false
     }
 
-    /// Returns the physical type of this device. Unlike [`Device::capabilities`]
+    /// Returns the name of this physical device, e.g. "Wacom Intuos Pro L".
+    /// The name is, in order of preference:
+    /// - the `NAME`, `HID_NAME` or `ID_MODEL` udev property of the device
+    ///   that all children hang off, see [`PhysicalDevice::match_device`]
+    /// - the common prefix of the names of all children, with any
+    ///   HID application suffix like "Pen" or "Mouse" removed
+    ///
+    /// The name is stable, removing a child does not change the name.
+    /// A device without a name returns the empty string.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the physical type of this device. Unlike [`HasCapability::capabilities`]
     /// a device is only of one physical type even where it supports multiple different
     /// input methods.
     ///
@@ -54,114 +262,901 @@ impl PhysicalDevice {
     /// For this reason this function returns a vector of types, with the most recently added
     /// abstract type first. A caller is expected to iterate
     /// through this vector matching against each element until the first element they know.
+    ///
+    /// The vector is empty if none of our capabilities decide the abstract type,
+    /// e.g. for a device without any known capabilities.
     pub fn abstract_types(&self) -> Vec<AbstractType> {
         self.abstract_types.clone()
     }
 
+    /// Returns the physical type of this device, a refinement of the first of
+    /// [`PhysicalDevice::abstract_types`] based on this device's capabilities.
+    /// For example, a [`AbstractType::Pointer`] may be a [`DeviceType::Mouse`] or
+    /// a [`DeviceType::Trackball`].
+    ///
+    /// Where the capabilities do not allow for a decision, this function
+    /// returns `None` rather than guessing. In particular, a device that is only
+    /// a [`Capability::Pointer`] is only a [`DeviceType::Mouse`] if it is in the
+    /// internal database of well-known devices or its name says so, many
+    /// trackballs are indistinguishable from a mouse.
+    pub fn device_type(&self) -> Option<DeviceType> {
+        let has = |c: Capability| self.caps.contains(&c);
+        match self.abstract_types.first()? {
+            // A numpad only has keys, a keyboard with a numpad isn't one
+            AbstractType::Keyboard if has(Capability::Numpad) => Some(DeviceType::Numpad),
+            AbstractType::Keyboard => Some(DeviceType::Keyboard),
+            AbstractType::Touchscreen => Some(DeviceType::Touchscreen),
+            AbstractType::Tablet => Some(DeviceType::Tablet),
+            AbstractType::GamingDevice => {
+                // udev tags gamepads and wheels as joysticks too
+                if has(Capability::RacingWheel) {
+                    Some(DeviceType::RacingWheel)
+                } else if has(Capability::Gamepad) {
+                    Some(DeviceType::Gamepad)
+                } else if has(Capability::Joystick) {
+                    Some(DeviceType::Joystick)
+                } else {
+                    None
+                }
+            }
+            AbstractType::Pointer => {
+                let candidates = [
+                    (has(Capability::Trackball), DeviceType::Trackball),
+                    (has(Capability::Pointingstick), DeviceType::Pointingstick),
+                    (has(Capability::Touchpad), DeviceType::Touchpad),
+                ];
+                let mut specific = candidates.iter().filter(|(has, _)| *has);
+                match (specific.next(), specific.next()) {
+                    (Some((_, dtype)), None) => Some(*dtype),
+                    // e.g. a trackball with a touchpad, we can't tell
+                    (Some(_), Some(_)) => None,
+                    (None, _) => {
+                        let is_mouse = self.sources.contains(&Source::Quirk)
+                            || self.name.to_lowercase().contains("mouse");
+                        is_mouse.then_some(DeviceType::Mouse)
+                    }
+                }
+            }
+            AbstractType::Switch => None,
+            AbstractType::Dial => Some(DeviceType::Dial),
+            AbstractType::Sensor => None,
+        }
+    }
+
     pub(crate) fn index(&self) -> DeviceIndex {
         self.node.idx.clone()
     }
 
-    /// Reduce our capabilities to one abstract type.
-    fn calculate_abstract_type(&mut self) -> AbstractType {
-        self.caps.iter().fold(AbstractType::Switch, |at, c| {
-            match c {
-                // A lot of keyboard-like devices also have a switch, so we only
-                // use the switch type for something that's *just* a switch
-                Capability::Switch => at,
-                // We only override to keyboard if we have a switch, otherwise
-                // we keep whatever we have.
-                Capability::Keyboard => {
-                    match at {
-                        AbstractType::Switch => AbstractType::Keyboard,
-                        _ => at,
-                    }
-                },
-                Capability::Pointer => {
-                    // If it's a keyboard and has pointer caps, it's probably a pointer.
-                    // Otherwise if it's anything more sophisticated, stick with what we have
-                    match at {
-                        AbstractType::Keyboard => at,
-                        _ => AbstractType::Pointer,
-                    }
-                }
-                // The ones below are very specific, if we have those set
-                // that's probably the device we have
-                Capability::Pointingstick => AbstractType::Pointer,
-                Capability::Touchpad => AbstractType::Pointer,
-                Capability::Clickpad => AbstractType::Pointer,
-                Capability::Pressurepad => AbstractType::Pointer,
-                Capability::Touchscreen => AbstractType::Touchscreen,
-                Capability::Trackball => AbstractType::Pointer,
-                Capability::Joystick => AbstractType::GamingDevice,
-                Capability::Gamepad => AbstractType::GamingDevice,
-                Capability::Tablet => AbstractType::Tablet,
-                Capability::TabletScreen => AbstractType::Tablet,
-                Capability::TabletExternal => AbstractType::Tablet,
-                Capability::TabletPad => AbstractType::Tablet,
+    /// Returns how confident we are in the classification of this device,
+    /// in the range `[0.0, 1.0]`.
+    ///
+    /// The confidence is derived from the most reliable source that contributed
+    /// to this device's capabilities: a device in the internal database of
+    /// well-known devices scores `1.0`, a device classified from its udev
+    /// properties or the kernel scores `0.9` and a device classified only
+    /// from its name scores `0.3`. A device without any capabilities scores `0.0`.
+    ///
+    /// A device with contradictory capabilities (see [`Capability::conflicts_with`]),
+    /// e.g. both [`Capability::Touchscreen`] and [`Capability::TabletExternal`],
+    /// has its confidence halved.
+    pub fn confidence(&self) -> f32 {
+        let confidence = self
+            .sources
+            .iter()
+            .map(|s| s.confidence())
+            .fold(0.0, f32::max);
+
+        if self
+            .caps
+            .iter()
+            .any(|a| self.caps.iter().any(|b| a.conflicts_with(*b)))
+        {
+            confidence * 0.5
+        } else {
+            confidence
+        }
+    }
+
+    /// Returns how confident we are that this device has the given capability,
+    /// in the range `[0.0, 1.0]`. This is the per-capability counterpart of
+    /// [`PhysicalDevice::confidence`], e.g. a touchpad may be certain because
+    /// udev tagged it while its clickpad capability is a guess from its name.
+    ///
+    /// Where multiple children have the capability, the most reliable source
+    /// counts. A capability only implied by others, e.g. the
+    /// [`Capability::Pointer`] of a [`Capability::Touchpad`], is only as
+    /// reliable as the least reliable source of its device. A device restored
+    /// with [`PhysicalDevice::deserialize`] reports its overall confidence.
+    ///
+    /// Returns `None` if this device does not have the capability.
+    pub fn capability_confidence(&self, cap: Capability) -> Option<f32> {
+        if !self.caps.contains(&cap) {
+            return None;
+        }
+        match self.capability_sources.get(&cap) {
+            Some(source) => Some(source.confidence()),
+            None => Some(self.confidence()),
+        }
+    }
+
+    /// Reduce our capabilities to one abstract type, see [`ABSTRACT_TYPE_PRIORITY`].
+    /// The result only depends on the capabilities, not on the order the
+    /// children were added in. Returns `None` if we have no capabilities
+    /// that decide the abstract type.
+    fn calculate_abstract_type(&self) -> Option<AbstractType> {
+        // A touchscreen with a stylus, e.g. a 2-in-1, is still a touchscreen.
+        // It's only a tablet if it has tablet-specific capabilities.
+        let has_tablet_caps = [
+            Capability::TabletScreen,
+            Capability::TabletExternal,
+            Capability::TabletPad,
+        ]
+        .iter()
+        .any(|c| self.caps.contains(c));
+        if self.caps.contains(&Capability::Touchscreen)
+            && self.caps.contains(&Capability::Stylus)
+            && !has_tablet_caps
+        {
+            log::debug!("{}: Touchscreen, a touchscreen with a stylus", self.name);
+            return Some(AbstractType::Touchscreen);
+        }
+
+        let (cap, atype) = match ABSTRACT_TYPE_PRIORITY
+            .iter()
+            .find(|(cap, _)| self.caps.contains(cap))
+        {
+            Some((cap, atype)) => (cap, atype.clone()),
+            None => {
+                log::debug!("{}: no abstract type for {:?}", self.name, self.caps);
+                return None;
             }
-        })
+        };
+
+        // A keyboard with a pointingstick but no other pointing device,
+        // e.g. a ThinkPad keyboard, is primarily a keyboard
+        let has_other_pointers = [
+            Capability::Touchpad,
+            Capability::Clickpad,
+            Capability::Pressurepad,
+            Capability::Trackball,
+        ]
+        .iter()
+        .any(|c| self.caps.contains(c));
+        if matches!(atype, AbstractType::Pointer)
+            && self.caps.contains(&Capability::Keyboard)
+            && self.caps.contains(&Capability::Pointingstick)
+            && !has_other_pointers
+        {
+            log::debug!("{}: Keyboard, a keyboard with a pointingstick", self.name);
+            return Some(AbstractType::Keyboard);
+        }
+
+        log::debug!("{}: {atype:?} from {cap:?} in {:?}", self.name, self.caps);
+        Some(atype)
     }
 
     pub(crate) fn add_child(&mut self, child: &AttachedDevice) {
         match child {
             AttachedDevice::Evdev(device) => {
                 self.children.push(device.index());
+                self.evdev_children.push((
+                    device.index(),
+                    device.name().to_string(),
+                    device.capabilities(),
+                ));
                 self.set_syspath(child);
+                if self.id_path.is_none() {
+                    self.id_path = device.udev_property("ID_PATH").filter(|p| !p.is_empty());
+                }
+                if self.phys.is_none() {
+                    self.phys = device
+                        .phys()
+                        .as_deref()
+                        .and_then(phys_prefix)
+                        .map(String::from);
+                }
+            }
+            AttachedDevice::Hidraw(device) => {
+                self.children.push(device.index());
+                self.set_syspath(child);
+            }
+            AttachedDevice::Parent(device) => {
+                panic!("Cannot attach a parent to a parent");
+            }
+        }
+        self.merge_capabilities(child);
+    }
+
+    /// Merge the name and capabilities of the given device into ours and
+    /// recalculate our abstract type, without linking the device as our child.
+    pub(crate) fn merge_capabilities(&mut self, child: &AttachedDevice) {
+        let quirk = match child {
+            AttachedDevice::Evdev(device) => {
+                self.add_name(device.name());
                 for cap in device.capabilities().iter() {
                     self.caps.insert(*cap);
                 }
+                self.sources.extend(device.sources().iter());
+                for (cap, source) in device.capability_sources() {
+                    self.add_capability_source(*cap, *source);
+                }
+                device.quirk()
+            }
+            AttachedDevice::Hidraw(device) => {
+                self.add_name(device.name());
+                let caps = device.capabilities();
+                // A hidraw device without any known application collections
+                // cannot contribute to our abstract type
+                if caps.is_empty() {
+                    return;
+                }
+                for cap in caps.iter() {
+                    self.add_capability_source(*cap, Source::Kernel);
+                }
+                self.caps.extend(caps);
+                self.sources.insert(Source::Kernel);
+                // hidraw doesn't give us the version
+                quirks::quirk_for(&self.quirks, device.vid(), device.pid(), None)
             }
             AttachedDevice::Parent(device) => {
-                panic!("Cannot attach a parent to a parent");
+                panic!("Cannot merge a parent into a parent");
             }
+        };
+
+        // Now let's see if we can calculate our abstract type, unless
+        // the device is well-known. Our type is recalculated from all
+        // children so it does not depend on the order they were added in.
+        if let Some(atype) = quirk.and_then(|q| q.abstract_type()) {
+            self.quirk_type = Some(atype);
         }
+        let atype = match &self.quirk_type {
+            Some(atype) => Some(atype.clone()),
+            None => self.calculate_abstract_type(),
+        };
+        self.abstract_types = atype.into_iter().collect();
+    }
+
+    /// Record the source of a capability, keeping the most reliable one where
+    /// multiple children have the same capability
+    fn add_capability_source(&mut self, cap: Capability, source: Source) {
+        let entry = self.capability_sources.entry(cap).or_insert(source);
+        *entry = (*entry).max(source);
+    }
 
-        // Now let's see if we can calculate our abstract type
-        let atype = self.calculate_abstract_type();
-        self.abstract_types.push(atype);
+    /// Unlink the child. Our capabilities still include the child's until
+    /// they are recalculated, see [`PhysicalDevice::recalculate`].
+    pub(crate) fn remove_child(&mut self, idx: &DeviceIndex) {
+        self.children.retain(|c| c != idx);
+        self.evdev_children.retain(|(c, _, _)| c != idx);
     }
 
+    /// Recalculate our capabilities, sources and abstract type from the
+    /// given children, e.g. after a child was removed. Our name is stable
+    /// and does not change.
+    pub(crate) fn recalculate<'a>(&mut self, children: impl Iterator<Item=&'a AttachedDevice>) {
+        self.caps.clear();
+        self.sources.clear();
+        self.capability_sources.clear();
+        self.quirk_type = None;
+        self.abstract_types.clear();
+        for child in children {
+            self.merge_capabilities(child);
+        }
+    }
+
+    pub(crate) fn set_node(&mut self, node: Node) {
+        self.node = node;
+    }
+
+    /// Link the child without re-evaluating our capabilities, for restoring
+    /// a device from a serialized [`DeviceTree`].
+    pub(crate) fn restore_child(&mut self, idx: DeviceIndex) {
+        self.children.push(idx);
+    }
+
+    /// Merge the child's name into our name, unless we have a name from udev.
+    fn add_name(&mut self, name: &str) {
+        if self.name_from_udev {
+            return;
+        }
+        let name = strip_application_suffix(name);
+        if self.name.is_empty() {
+            self.name = name.to_string();
+        } else {
+            let prefix = common_prefix(&self.name, name);
+            if !prefix.is_empty() {
+                self.name = prefix;
+            }
+        }
+    }
+
+    /// Without udev we cannot anchor to a device in sysfs, so matching
+    /// devices is left to the caller.
+    #[cfg(not(feature = "udev"))]
+    fn set_syspath(&mut self, _child: &AttachedDevice) {}
+
+    #[cfg(feature = "udev")]
     fn set_syspath(&mut self, child: &AttachedDevice) {
         if self.sysfs.is_some() {
             return;
         }
 
-        let evdev = match child {
-            AttachedDevice::Evdev(ref device) => device,
+        let evdev_sysfs = match child {
+            AttachedDevice::Evdev(ref device) => match device.sysfs_path() {
+                Some(path) => path,
+                // Not a kernel device, nothing to anchor to
+                None => return,
+            },
+            AttachedDevice::Hidraw(ref device) => {
+                // hidraw nodes hang directly off the HID device. The device
+                // may have been removed since it was created
+                let device = match udev::Device::from_syspath(device.sysfs_path()) {
+                    Ok(device) => device,
+                    Err(_) => return,
+                };
+                if let Some(parent) = hid_ancestor(&device).or_else(|| device.parent()) {
+                    self.set_anchor(&parent);
+                }
+                return;
+            }
             _ => {
                 panic!("Not implemented");
             }
         };
-        let device =
-            udev::Device::from_syspath(evdev.sysfs_path()).expect("Unable to find udev device");
-        let syspath: Option<PathBuf> = loop {
-            let parent = device.parent();
-            if parent.is_none() {
-                break None;
-            }
-            let parent = parent.unwrap();
-            match parent.subsystem() {
-                Some(str) if str == "input" => {
-                    // we go up one from input to find the real device
-                    let grandparent = parent.parent().or(Some(parent)).unwrap();
-                    break Some(grandparent.syspath().to_owned());
-                }
-                _ => {},
-            };
+        // A replayed device (see Capture) may not exist on this machine
+        let device = match udev::Device::from_syspath(evdev_sysfs) {
+            Ok(device) => device,
+            Err(_) => return,
+        };
+        if let Some(anchor) = evdev_anchor(&device) {
+            self.set_anchor(&anchor);
+        }
+    }
+
+    /// Use the given udev device as the device all our children hang off.
+    #[cfg(feature = "udev")]
+    fn set_anchor(&mut self, anchor: &udev::Device) {
+        self.sysfs = Some(anchor.syspath().to_owned());
+
+        self.usb_device = usb_device(anchor).map(|d| d.syspath().to_owned());
+
+        let name = ["NAME", "HID_NAME", "ID_MODEL"].iter().find_map(|prop| {
+            anchor
+                .property_value(prop)
+                .and_then(|v| v.to_str())
+                .map(|v| v.trim_matches('"').replace('_', " "))
+                .filter(|v| !v.is_empty())
+        });
+        if let Some(name) = name {
+            self.name = name;
+            self.name_from_udev = true;
+        }
+    }
+
+    /// Returns the evdev child that is the primary node for the given
+    /// capability, e.g. "the" keyboard node of a keyboard that also has a
+    /// consumer control node with keys.
+    ///
+    /// Where multiple children have the capability, a child whose name does
+    /// not end in a secondary HID application suffix like "Consumer Control"
+    /// or "System Control" is preferred, then the child added first.
+    ///
+    /// Returns `None` if none of our evdev children has the capability.
+    pub fn primary_for(&self, cap: Capability) -> Option<DeviceIndex> {
+        let candidates = self
+            .evdev_children
+            .iter()
+            .filter(|(_, _, caps)| caps.contains(&cap));
+        let is_secondary = |name: &str| {
+            SECONDARY_APPLICATION_SUFFIXES
+                .iter()
+                .any(|s| name.ends_with(s))
         };
+        candidates
+            .clone()
+            .find(|(_, name, _)| !is_secondary(name))
+            .or_else(|| candidates.clone().next())
+            .map(|(idx, _, _)| *idx)
+    }
 
-        self.sysfs = syspath;
+    /// Returns the evdev child that is the pad of a tablet, i.e. the node
+    /// with the buttons, rings and strips on the tablet itself. A tablet's
+    /// pad, pen and touch nodes share a parent, so the pad of the tablet
+    /// is the pad of its parent.
+    ///
+    /// ```
+    /// use whodat::{Builder, Capability, DeviceTree, HasParent};
+    ///
+    /// let mut tree = DeviceTree::new();
+    /// let mut attach = |name: &str, interface: u8, prop: &str| {
+    ///     let device = Builder::new()
+    ///         .name(name)
+    ///         .usbid(0x056a, 0x0358)
+    ///         .phys(&format!("usb-0000:00:14.0-1/input{interface}"))
+    ///         .udev_properties(vec![(prop.to_string(), "1".to_string())])
+    ///         .build()
+    ///         .unwrap();
+    ///     tree.attach_evdev_device(device)
+    /// };
+    /// let pen = attach("Wacom Intuos Pro L Pen", 0, "ID_INPUT_TABLET");
+    /// let touch = attach("Wacom Intuos Pro L Finger", 1, "ID_INPUT_TOUCHPAD");
+    /// let pad = attach("Wacom Intuos Pro L Pad", 0, "ID_INPUT_TABLET_PAD");
+    ///
+    /// let pidx = tree.get_evdev_device(&pen).unwrap().parent();
+    /// assert_eq!(tree.get_evdev_device(&touch).unwrap().parent(), pidx);
+    /// assert_eq!(tree.get_evdev_device(&pad).unwrap().parent(), pidx);
+    ///
+    /// let parent = tree.get_parent_device(&pidx).unwrap();
+    /// assert_eq!(parent.tablet_pad(), Some(pad));
+    /// assert_eq!(parent.primary_for(Capability::Tablet), Some(pen));
+    /// ```
+    ///
+    /// Returns `None` if none of our evdev children is a tablet pad.
+    pub fn tablet_pad(&self) -> Option<DeviceIndex> {
+        self.primary_for(Capability::TabletPad)
     }
 
-    /// Returns an iterator over all children of this parent device
+    /// Returns an iterator over the [`DeviceIndex`] of all children of this
+    /// parent device. The children are owned by the [`DeviceTree`], use
+    /// [`DeviceTree::children`] to iterate over the devices themselves.
     pub fn iter(&self) -> impl Iterator<Item=&DeviceIndex> + '_ {
         self.children.iter()
     }
 }
 
+/// Two [`PhysicalDevice`]s are equal if they have the same [`DeviceIndex`],
+/// i.e. a clone is equal to the device it was cloned from.
+impl PartialEq for PhysicalDevice {
+    fn eq(&self, other: &Self) -> bool {
+        self.index() == other.index()
+    }
+}
+
 impl HasCapability for PhysicalDevice {
     fn capabilities(&self) -> Vec<Capability> {
         self.caps.iter().map(|c| c.clone()).collect()
     }
 }
+
+impl Device for PhysicalDevice {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn physical_parent(&self) -> Option<DeviceIndex> {
+        None
+    }
+}
+
+/// Strip the interface-specific `/inputN` suffix from a physical path, e.g.
+/// `usb-0000:00:14.0-2/input0` becomes `usb-0000:00:14.0-2`.
+///
+/// Paths without such a suffix return `None`. Bluetooth devices use the
+/// adapter's address as physical path, so it is shared by all devices
+/// connected to that adapter.
+fn phys_prefix(phys: &str) -> Option<&str> {
+    match phys.rsplit_once('/') {
+        Some((prefix, suffix)) if suffix.starts_with("input") && !prefix.is_empty() => Some(prefix),
+        _ => None,
+    }
+}
+
+/// Return the device an evdev device's [`PhysicalDevice`] is anchored to,
+/// see [`PhysicalDevice::match_device`].
+#[cfg(feature = "udev")]
+pub(crate) fn evdev_anchor(device: &udev::Device) -> Option<udev::Device> {
+    // HID devices have hidraw and evdev nodes below the same hid device,
+    // anchoring there groups all of them. The input device may be
+    // further down than that.
+    if let Some(hid) = hid_ancestor(device) {
+        return Some(hid);
+    }
+    let mut device = device.clone();
+    loop {
+        let parent = device.parent()?;
+        if parent.subsystem().map_or(false, |s| s == "input") {
+            // we go up one from input to find the real device
+            return Some(parent.parent().unwrap_or(parent));
+        }
+        device = parent;
+    }
+}
+
+/// Return the USB device the device is or hangs off, if any
+#[cfg(feature = "udev")]
+pub(crate) fn usb_device(device: &udev::Device) -> Option<udev::Device> {
+    let is_usb_device = |d: &udev::Device| d.devtype().map_or(false, |t| t == "usb_device");
+    if is_usb_device(device) {
+        return Some(device.clone());
+    }
+    let mut device = device.parent();
+    while let Some(d) = device {
+        if is_usb_device(&d) {
+            return Some(d);
+        }
+        device = d.parent();
+    }
+    None
+}
+
+/// Return the closest ancestor of the device in the `hid` subsystem, if any.
+/// The search stops at the USB device, a HID device is never above it.
+#[cfg(feature = "udev")]
+fn hid_ancestor(device: &udev::Device) -> Option<udev::Device> {
+    let mut device = device.parent();
+    while let Some(d) = device {
+        match d.subsystem().and_then(|s| s.to_str()) {
+            Some("hid") => return Some(d),
+            Some("usb") => return None,
+            _ => device = d.parent(),
+        }
+    }
+    None
+}
+
+/// The suffixes the kernel appends to the name of a HID device for
+/// each HID application, see [`Application`].
+const APPLICATION_SUFFIXES: &[&str] = &[
+    "Consumer Control",
+    "System Control",
+    "Keyboard",
+    "Keypad",
+    "Mouse",
+    "Touchpad",
+    "Pen",
+    "Finger",
+    "Pad",
+    "Stylus",
+];
+
+/// The [`APPLICATION_SUFFIXES`] of nodes that are only the primary node of
+/// a device if no other node qualifies, see [`PhysicalDevice::primary_for`]
+const SECONDARY_APPLICATION_SUFFIXES: &[&str] = &["Consumer Control", "System Control"];
+
+/// Strip any HID application suffix from a device name, e.g.
+/// "Wacom Intuos Pro L Pen" becomes "Wacom Intuos Pro L".
+fn strip_application_suffix(name: &str) -> &str {
+    APPLICATION_SUFFIXES
+        .iter()
+        .find_map(|suffix| name.strip_suffix(suffix))
+        .map(|n| n.trim_end())
+        .filter(|n| !n.is_empty())
+        .unwrap_or(name)
+}
+
+/// The common prefix of both names, in whole words.
+fn common_prefix(a: &str, b: &str) -> String {
+    a.split_whitespace()
+        .zip(b.split_whitespace())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a)
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phys_prefix() {
+        assert_eq!(
+            phys_prefix("usb-0000:00:14.0-2/input0"),
+            Some("usb-0000:00:14.0-2")
+        );
+        assert_eq!(
+            phys_prefix("usb-0000:00:14.0-2/input12"),
+            Some("usb-0000:00:14.0-2")
+        );
+        // Bluetooth devices share the adapter address
+        assert_eq!(phys_prefix("a4:c3:f0:12:34:56"), None);
+        assert_eq!(phys_prefix("isa0060/serio0"), None);
+        assert_eq!(phys_prefix("/input0"), None);
+        assert_eq!(phys_prefix(""), None);
+    }
+
+    /// An evdev device of a device without quirks
+    fn evdev(name: &str, udev_properties: &[&str]) -> EvdevDevice {
+        EvdevDevice::from_parts(name, 0x1234, 0x5678, udev_properties, &[], None)
+    }
+
+    /// The abstract type of a physical device with the given children,
+    /// added in the given order
+    fn abstract_type(children: &[&EvdevDevice]) -> Option<AbstractType> {
+        let mut parent = PhysicalDevice::new();
+        for child in children {
+            parent.merge_capabilities(&AttachedDevice::Evdev((*child).clone()));
+        }
+        parent.abstract_types().first().cloned()
+    }
+
+    #[test]
+    fn test_abstract_type_order_independent() {
+        let pen = evdev("Wacom Cintiq 16 Pen", &["ID_INPUT_TABLET"]);
+        let touch = evdev("Wacom Cintiq 16 Finger", &["ID_INPUT_TOUCHSCREEN"]);
+        let pad = evdev("Wacom Cintiq 16 Pad", &["ID_INPUT_TABLET_PAD"]);
+        for children in [
+            [&pen, &touch, &pad],
+            [&touch, &pen, &pad],
+            [&pad, &touch, &pen],
+            [&touch, &pad, &pen],
+        ] {
+            assert!(matches!(
+                abstract_type(&children),
+                Some(AbstractType::Tablet)
+            ));
+        }
+
+        let gamepad = evdev("Generic Controller", &["ID_INPUT_JOYSTICK"]);
+        let touchpad = evdev("Generic Controller Touchpad", &["ID_INPUT_TOUCHPAD"]);
+        let motion = evdev(
+            "Generic Controller Motion Sensors",
+            &["ID_INPUT_ACCELEROMETER"],
+        );
+        for children in [
+            [&gamepad, &touchpad, &motion],
+            [&touchpad, &motion, &gamepad],
+            [&motion, &gamepad, &touchpad],
+        ] {
+            assert!(matches!(
+                abstract_type(&children),
+                Some(AbstractType::GamingDevice)
+            ));
+        }
+    }
+
+    /// Attach the device with the given physical path, returns the index
+    /// of its parent
+    fn attach_with_phys(tree: &mut DeviceTree, mut device: EvdevDevice, phys: &str) -> DeviceIndex {
+        device.set_phys(phys);
+        let idx = tree.attach_evdev_device(device);
+        tree.get_evdev_device(&idx).unwrap().parent()
+    }
+
+    #[test]
+    fn test_match_sibling_interfaces() {
+        let mut tree = DeviceTree::new();
+        // A keyboard with a touchpad on a second USB interface
+        let keyboard = evdev("Logitech K400 Keyboard", &["ID_INPUT_KEYBOARD"]);
+        let touchpad = evdev("Logitech K400 Touchpad", &["ID_INPUT_TOUCHPAD"]);
+        let mouse = evdev("Logitech USB Optical Mouse", &["ID_INPUT_MOUSE"]);
+        let p1 = attach_with_phys(&mut tree, keyboard, "usb-0000:00:14.0-2/input0");
+        let p2 = attach_with_phys(&mut tree, touchpad, "usb-0000:00:14.0-2/input1");
+        let p3 = attach_with_phys(&mut tree, mouse, "usb-0000:00:14.0-3/input0");
+        assert_eq!(p1, p2);
+        assert_ne!(p1, p3);
+
+        let parent = tree.get_parent_device(&p1).unwrap();
+        assert_eq!(parent.iter().count(), 2);
+        assert!(parent.has(Capability::Keyboard));
+        assert!(parent.has(Capability::Touchpad));
+        assert!(matches!(
+            parent.abstract_types()[..],
+            [AbstractType::Pointer]
+        ));
+        assert_eq!(tree.get_parent_device(&p3).unwrap().iter().count(), 1);
+    }
+
+    #[test]
+    fn test_no_match_bluetooth_phys() {
+        let mut tree = DeviceTree::new();
+        let keyboard = evdev("Keychron K2", &["ID_INPUT_KEYBOARD"]);
+        let mouse = evdev("MX Master 3", &["ID_INPUT_MOUSE"]);
+        // Bluetooth devices have the adapter's address as physical path
+        let p1 = attach_with_phys(&mut tree, keyboard, "a4:c3:f0:12:34:56");
+        let p2 = attach_with_phys(&mut tree, mouse, "a4:c3:f0:12:34:56");
+        assert_ne!(p1, p2);
+    }
+
+    #[test]
+    fn test_match_hid_anchor() {
+        // A DualSense's gamepad, touchpad and hidraw nodes all hang off the HID device
+        let hid = "/sys/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.3/0003:054C:0CE6.0005";
+        let evdev = |name: &str, props: &[&str], node: &str| {
+            let sysfs = PathBuf::from(format!("{hid}/{node}"));
+            AttachedDevice::Evdev(EvdevDevice::from_parts(
+                name,
+                0x054c,
+                0x0ce6,
+                props,
+                &[],
+                Some(sysfs),
+            ))
+        };
+        let name = "Sony Interactive Entertainment DualSense Wireless Controller";
+        let gamepad = evdev(name, &["ID_INPUT_JOYSTICK"], "input/input20/event18");
+        let touchpad = evdev(name, &["ID_INPUT_TOUCHPAD"], "input/input21/event19");
+        let hidraw = AttachedDevice::Hidraw(HidrawDevice::from_parts(
+            name.to_string(),
+            (BusType::Usb, 0x054c, 0x0ce6),
+            None,
+            PathBuf::from(format!("{hid}/hidraw/hidraw3")),
+            &[0x05, 0x01, 0x09, 0x05, 0xa1, 0x01, 0xc0],
+        ));
+        // A mouse on a different USB port
+        let other = "/sys/devices/pci0000:00/0000:00:14.0/usb1/1-3/1-3:1.0/0003:046D:C077.0006";
+        let mouse = AttachedDevice::Evdev(EvdevDevice::from_parts(
+            "Logitech USB Optical Mouse",
+            0x046d,
+            0xc077,
+            &["ID_INPUT_MOUSE"],
+            &[],
+            Some(PathBuf::from(format!("{other}/input/input22/event20"))),
+        ));
+
+        let mut parent = PhysicalDevice::new();
+        parent.sysfs = Some(PathBuf::from(hid));
+        for child in [&gamepad, &touchpad, &hidraw] {
+            assert!(parent.match_device(child));
+            parent.add_child(child);
+        }
+        assert!(!parent.match_device(&mouse));
+
+        assert_eq!(parent.iter().count(), 3);
+        assert!(parent.has(Capability::Gamepad));
+        assert!(parent.has(Capability::Touchpad));
+        assert!(matches!(
+            parent.abstract_types()[..],
+            [AbstractType::GamingDevice]
+        ));
+    }
+
+    #[test]
+    fn test_keyboard_with_pointingstick() {
+        let keyboard = evdev(
+            "Lenovo ThinkPad Compact USB Keyboard with TrackPoint",
+            &["ID_INPUT_KEY", "ID_INPUT_KEYBOARD"],
+        );
+        let trackpoint = evdev(
+            "Lenovo ThinkPad Compact USB Keyboard with TrackPoint Mouse",
+            &["ID_INPUT_MOUSE", "ID_INPUT_POINTINGSTICK"],
+        );
+        let touchpad = evdev(
+            "Lenovo ThinkPad TrackPoint Keyboard II Touchpad",
+            &["ID_INPUT_TOUCHPAD"],
+        );
+        for children in [[&keyboard, &trackpoint], [&trackpoint, &keyboard]] {
+            assert!(matches!(
+                abstract_type(&children),
+                Some(AbstractType::Keyboard)
+            ));
+        }
+        // Not a plain keyboard though
+        let mut parent = PhysicalDevice::new();
+        for child in [&keyboard, &trackpoint] {
+            parent.merge_capabilities(&AttachedDevice::Evdev(child.clone()));
+        }
+        assert!(parent.has(Capability::Pointingstick));
+        assert!(parent.has(Capability::Pointer));
+        assert_eq!(parent.device_type(), Some(DeviceType::Keyboard));
+
+        // With a touchpad it's primarily a pointer device
+        assert!(matches!(
+            abstract_type(&[&keyboard, &trackpoint, &touchpad]),
+            Some(AbstractType::Pointer)
+        ));
+        assert!(matches!(
+            abstract_type(&[&keyboard]),
+            Some(AbstractType::Keyboard)
+        ));
+    }
+
+    #[test]
+    fn test_switch_and_keyboard() {
+        let lid = evdev("Lid Switch", &["ID_INPUT_SWITCH"]);
+        assert!(matches!(abstract_type(&[&lid]), Some(AbstractType::Switch)));
+
+        // Media keys only, udev doesn't tag it as a full keyboard
+        let media = evdev("Dell WMI hotkeys", &["ID_INPUT_KEY"]);
+        assert!(matches!(
+            abstract_type(&[&media]),
+            Some(AbstractType::Keyboard)
+        ));
+
+        let keyboard = evdev(
+            "AT Translated Set 2 keyboard",
+            &["ID_INPUT_KEY", "ID_INPUT_KEYBOARD"],
+        );
+        assert!(matches!(
+            abstract_type(&[&keyboard]),
+            Some(AbstractType::Keyboard)
+        ));
+
+        // e.g. the ThinkPad Extra Buttons with SW_TABLET_MODE
+        let buttons = evdev(
+            "ThinkPad Extra Buttons",
+            &["ID_INPUT_KEY", "ID_INPUT_SWITCH"],
+        );
+        assert!(matches!(
+            abstract_type(&[&buttons]),
+            Some(AbstractType::Keyboard)
+        ));
+        assert!(matches!(
+            abstract_type(&[&lid, &media]),
+            Some(AbstractType::Keyboard)
+        ));
+
+        let nothing = evdev("Unknown", &[]);
+        assert!(abstract_type(&[&nothing]).is_none());
+    }
+
+    #[test]
+    fn test_trackpoint_and_touchpad() {
+        // A ThinkPad, udev only tags the trackpoint as a mouse
+        let trackpoint = evdev("TPPS/2 IBM TrackPoint", &["ID_INPUT_MOUSE"]);
+        assert!(trackpoint.has(Capability::Pointer));
+        assert!(trackpoint.has(Capability::Pointingstick));
+        let touchpad = evdev("SynPS/2 Synaptics TouchPad", &["ID_INPUT_TOUCHPAD"]);
+
+        let mut tree = DeviceTree::new();
+        let p1 = attach_with_phys(&mut tree, trackpoint, "isa0060/serio1/input1");
+        let p2 = attach_with_phys(&mut tree, touchpad, "isa0060/serio1/input0");
+        assert_eq!(p1, p2);
+        let parent = tree.get_parent_device(&p1).unwrap();
+        assert!(parent.has(Capability::Pointingstick));
+        assert!(parent.has(Capability::Touchpad));
+        assert!(matches!(
+            parent.abstract_types()[..],
+            [AbstractType::Pointer]
+        ));
+        // Two different pointers, we can't pick one
+        assert_eq!(parent.device_type(), None);
+
+        // The kernel knows it's a pointing stick, the name doesn't say so
+        let trackpoint = EvdevDevice::from_parts(
+            "PS/2 Generic Mouse",
+            0x1234,
+            0x5678,
+            &["ID_INPUT_MOUSE"],
+            &[InputProp::PointingStick],
+            None,
+        );
+        assert!(trackpoint.has(Capability::Pointingstick));
+        let mouse = evdev("Generic Pointer", &["ID_INPUT_MOUSE"]);
+        assert!(!mouse.has(Capability::Pointingstick));
+    }
+
+    #[test]
+    fn test_match_without_sysfs() {
+        let with_id_path = |name: &str, prop: &str, id_path: &str| {
+            let mut device = evdev(name, &[]);
+            device.set_udev_properties(&[
+                (prop.to_string(), "1".to_string()),
+                ("ID_PATH".to_string(), id_path.to_string()),
+            ]);
+            device
+        };
+        // Neither device has a sysfs path or a usable phys
+        let keyboard = with_id_path("Keyboard", "ID_INPUT_KEYBOARD", "platform-vkbd-1");
+        let touchpad = with_id_path("Touchpad", "ID_INPUT_TOUCHPAD", "platform-vkbd-1");
+        let mouse = with_id_path("Mouse", "ID_INPUT_MOUSE", "platform-vmouse-1");
+        assert!(keyboard.sysfs_path().is_none());
+        assert!(keyboard.phys().is_none());
+
+        let mut tree = DeviceTree::new();
+        let attach = |tree: &mut DeviceTree, device: EvdevDevice| {
+            let idx = tree.attach_evdev_device(device);
+            tree.get_evdev_device(&idx).unwrap().parent()
+        };
+        let p1 = attach(&mut tree, keyboard);
+        let p2 = attach(&mut tree, touchpad);
+        let p3 = attach(&mut tree, mouse);
+        assert_eq!(p1, p2);
+        assert_ne!(p1, p3);
+        let parent = tree.get_parent_device(&p1).unwrap();
+        assert!(parent.sysfs.is_none());
+        assert_eq!(parent.iter().count(), 2);
+
+        // Without ID_PATH we fall back to the phys
+        let keyboard = evdev("Keyboard", &["ID_INPUT_KEYBOARD"]);
+        let mouse = evdev("Mouse", &["ID_INPUT_MOUSE"]);
+        let p1 = attach_with_phys(&mut tree, keyboard, "virtual-1/input0");
+        let p2 = attach_with_phys(&mut tree, mouse, "virtual-1/input1");
+        assert_eq!(p1, p2);
+
+        // Nothing to group by, each device is on its own
+        let p1 = attach(&mut tree, evdev("Keyboard", &["ID_INPUT_KEYBOARD"]));
+        let p2 = attach(&mut tree, evdev("Mouse", &["ID_INPUT_MOUSE"]));
+        assert_ne!(p1, p2);
+    }
+}
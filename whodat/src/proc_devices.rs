@@ -0,0 +1,319 @@
+use crate::*;
+
+use std::path::PathBuf;
+
+/// The path to the kernel's list of input devices
+const PROC_BUS_INPUT_DEVICES: &str = "/proc/bus/input/devices";
+
+/// A [`ProcInputDevice`] is the record of one input device in
+/// `/proc/bus/input/devices`. That file is world-readable, so this is
+/// an alternative source of device information on systems where the caller
+/// cannot open the device nodes.
+///
+/// The kernel does not list udev properties, so a device created from
+/// this source is classified from its name, ids and supported event codes
+/// only, see [`EvdevDevice::from_source`].
+///
+/// ```no_run
+/// use whodat::{EvdevDevice, ProcInputDevice};
+///
+/// for record in ProcInputDevice::read().unwrap() {
+///     let device = EvdevDevice::from_source(&record);
+///     println!("{}: {:?}", device.name(), device.devnode());
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ProcInputDevice {
+    name: String,
+    bustype: u16,
+    vid: u16,
+    pid: u16,
+    version: u16,
+    uniq: Option<String>,
+    phys: Option<String>,
+    /// The `inputN` device in sysfs
+    sysfs: Option<PathBuf>,
+    handlers: Vec<String>,
+    input_properties: Vec<u16>,
+    keys: Vec<u16>,
+    abs_axes: Vec<u16>,
+    rel_axes: Vec<u16>,
+    switches: Vec<u16>,
+}
+
+impl ProcInputDevice {
+    /// Read and parse `/proc/bus/input/devices`.
+    pub fn read() -> Result<Vec<ProcInputDevice>, Error> {
+        let data = std::fs::read_to_string(PROC_BUS_INPUT_DEVICES)?;
+        Ok(Self::parse(&data))
+    }
+
+    /// Parse the contents of `/proc/bus/input/devices`. Records are separated
+    /// by an empty line, lines we don't know about are ignored.
+    pub fn parse(data: &str) -> Vec<ProcInputDevice> {
+        data.split("\n\n")
+            .filter(|record| !record.trim().is_empty())
+            .map(Self::parse_record)
+            .collect()
+    }
+
+    fn parse_record(record: &str) -> ProcInputDevice {
+        let mut device = ProcInputDevice::default();
+        for line in record.lines() {
+            let (kind, value) = match line.split_once(": ") {
+                Some((kind, value)) => (kind, value.trim()),
+                None => continue,
+            };
+            match kind {
+                // I: Bus=0003 Vendor=046d Product=c52b Version=0111
+                "I" => {
+                    let ids = value.split_whitespace().filter_map(|kv| kv.split_once('='));
+                    for (key, id) in ids {
+                        let id = u16::from_str_radix(id, 16).unwrap_or(0);
+                        match key {
+                            "Bus" => device.bustype = id,
+                            "Vendor" => device.vid = id,
+                            "Product" => device.pid = id,
+                            "Version" => device.version = id,
+                            _ => {}
+                        }
+                    }
+                }
+                // N: Name="Logitech USB Receiver"
+                "N" => {
+                    if let Some(name) = value.strip_prefix("Name=") {
+                        device.name = name.trim_matches('"').to_string();
+                    }
+                }
+                // P: Phys=usb-0000:00:14.0-2/input0
+                "P" => {
+                    device.phys = value
+                        .strip_prefix("Phys=")
+                        .filter(|p| !p.is_empty())
+                        .map(|p| p.to_string());
+                }
+                "U" => {
+                    device.uniq = value
+                        .strip_prefix("Uniq=")
+                        .filter(|u| !u.is_empty())
+                        .map(|u| u.to_string());
+                }
+                // S: Sysfs=/devices/pci0000:00/.../input/input5
+                "S" => {
+                    if let Some(path) = value.strip_prefix("Sysfs=") {
+                        device.sysfs = Some(PathBuf::from(format!("/sys{path}")));
+                    }
+                }
+                // H: Handlers=sysrq kbd event3 leds
+                "H" => {
+                    if let Some(handlers) = value.strip_prefix("Handlers=") {
+                        device.handlers = handlers.split_whitespace().map(String::from).collect();
+                    }
+                }
+                // B: KEY=1000000000007 ff9f207ac14057ff ...
+                "B" => match value.split_once('=') {
+                    Some(("PROP", mask)) => device.input_properties = util::parse_bitmask(mask),
+                    Some(("KEY", mask)) => device.keys = util::parse_bitmask(mask),
+                    Some(("ABS", mask)) => device.abs_axes = util::parse_bitmask(mask),
+                    Some(("REL", mask)) => device.rel_axes = util::parse_bitmask(mask),
+                    Some(("SW", mask)) => device.switches = util::parse_bitmask(mask),
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        device
+    }
+
+    /// The handlers attached to this device, e.g. `["kbd", "event3"]`
+    pub fn handlers(&self) -> &[String] {
+        &self.handlers
+    }
+
+    /// The `eventN` handler of this device, if any
+    fn event_handler(&self) -> Option<&str> {
+        self.handlers
+            .iter()
+            .map(|h| h.as_str())
+            .find(|h| h.starts_with("event"))
+    }
+}
+
+impl EvdevSource for ProcInputDevice {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn input_id(&self) -> (u16, u16, u16) {
+        (self.bustype, self.vid, self.pid)
+    }
+
+    fn version(&self) -> u16 {
+        self.version
+    }
+
+    fn uniq(&self) -> Option<String> {
+        self.uniq.clone()
+    }
+
+    fn phys(&self) -> Option<String> {
+        self.phys.clone()
+    }
+
+    fn udev_properties(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn input_properties(&self) -> Vec<u16> {
+        self.input_properties.clone()
+    }
+
+    fn keys(&self) -> Vec<u16> {
+        self.keys.clone()
+    }
+
+    fn abs_axes(&self) -> Vec<u16> {
+        self.abs_axes.clone()
+    }
+
+    fn rel_axes(&self) -> Vec<u16> {
+        self.rel_axes.clone()
+    }
+
+    fn switches(&self) -> Vec<u16> {
+        self.switches.clone()
+    }
+
+    fn size_mm(&self) -> Option<(u32, u32)> {
+        None
+    }
+
+    fn devnode(&self) -> Option<PathBuf> {
+        self.event_handler()
+            .map(|h| PathBuf::from(format!("/dev/input/{h}")))
+    }
+
+    /// The sysfs path of the `eventN` device, the `inputN` device's child
+    fn sysfs_path(&self) -> Option<PathBuf> {
+        self.sysfs
+            .as_ref()
+            .zip(self.event_handler())
+            .map(|(s, h)| s.join(h))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// From a laptop with an external mouse
+    const DEVICES: &str = r#"I: Bus=0019 Vendor=0000 Product=0005 Version=0000
+N: Name="Lid Switch"
+P: Phys=PNP0C0D/button/input0
+S: Sysfs=/devices/LNXSYSTM:00/LNXSYBUS:00/PNP0C0D:00/input/input0
+U: Uniq=
+H: Handlers=event0
+B: PROP=0
+B: EV=21
+B: SW=1
+
+I: Bus=0011 Vendor=0001 Product=0001 Version=ab83
+N: Name="AT Translated Set 2 keyboard"
+P: Phys=isa0060/serio0/input0
+S: Sysfs=/devices/platform/i8042/serio0/input/input3
+U: Uniq=
+H: Handlers=sysrq kbd leds event3
+B: PROP=0
+B: EV=120013
+B: KEY=402000000 3803078f800d001 feffffdfffefffff fffffffffffffffe
+B: MSC=10
+B: LED=7
+
+I: Bus=0003 Vendor=046d Product=c077 Version=0111
+N: Name="Logitech USB Optical Mouse"
+P: Phys=usb-0000:00:14.0-2/input0
+S: Sysfs=/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0/0003:046D:C077.0002/input/input5
+U: Uniq=
+H: Handlers=mouse0 event5
+B: PROP=0
+B: EV=17
+B: KEY=70000 0 0 0 0
+B: REL=903
+B: MSC=10
+
+I: Bus=0019 Vendor=0000 Product=0000 Version=0000
+N: Name="Virtual Device"
+P: Phys=
+S: Sysfs=/devices/virtual/input/input9
+U: Uniq=
+H: Handlers=
+B: PROP=0
+B: EV=1
+"#;
+
+    #[test]
+    fn test_parse() {
+        let records = ProcInputDevice::parse(DEVICES);
+        assert_eq!(records.len(), 4);
+
+        let lid = &records[0];
+        assert_eq!(lid.name(), "Lid Switch");
+        assert_eq!(lid.input_id(), (0x19, 0x0000, 0x0005));
+        assert_eq!(lid.handlers(), &["event0"]);
+        assert_eq!(lid.switches(), vec![0]);
+        assert_eq!(lid.uniq(), None);
+
+        let keyboard = &records[1];
+        assert_eq!(keyboard.input_id(), (0x11, 0x0001, 0x0001));
+        assert_eq!(keyboard.version(), 0xab83);
+        assert_eq!(keyboard.phys().as_deref(), Some("isa0060/serio0/input0"));
+        assert_eq!(keyboard.handlers(), &["sysrq", "kbd", "leds", "event3"]);
+        assert_eq!(keyboard.devnode(), Some(PathBuf::from("/dev/input/event3")));
+        assert_eq!(
+            keyboard.sysfs_path(),
+            Some(PathBuf::from(
+                "/sys/devices/platform/i8042/serio0/input/input3/event3"
+            ))
+        );
+        // The words of the mask are 64 bits each, the last one is the lowest
+        let keys = keyboard.keys();
+        assert_eq!(keys.len(), 144);
+        assert!((1..=57).all(|k| keys.contains(&k)));
+        assert!(keys.iter().all(|k| *k < 0x100));
+
+        let mouse = &records[2];
+        assert_eq!(mouse.input_id(), (0x03, 0x046d, 0xc077));
+        assert_eq!(mouse.keys(), vec![0x110, 0x111, 0x112]);
+        // REL_X, REL_Y, REL_WHEEL, REL_WHEEL_HI_RES
+        assert_eq!(mouse.rel_axes(), vec![0, 1, 8, 11]);
+        assert_eq!(mouse.devnode(), Some(PathBuf::from("/dev/input/event5")));
+
+        // Empty Phys=, Uniq= and Handlers=
+        let virt = &records[3];
+        assert_eq!(virt.phys(), None);
+        assert_eq!(virt.uniq(), None);
+        assert!(virt.handlers().is_empty());
+        assert_eq!(virt.devnode(), None);
+        assert_eq!(virt.sysfs_path(), None);
+        assert!(virt.keys().is_empty());
+    }
+
+    #[test]
+    fn test_classify() {
+        let devices: Vec<EvdevDevice> = ProcInputDevice::parse(DEVICES)
+            .iter()
+            .map(|record| EvdevDevice::from_source(record))
+            .collect();
+
+        assert!(devices[0].capabilities().is_empty());
+        assert_eq!(devices[0].switches(), vec![SwitchType::Lid]);
+        assert_eq!(devices[1].capabilities(), vec![Capability::Keyboard]);
+        assert_eq!(devices[1].sources(), &vec![Source::Kernel]);
+        assert_eq!(devices[2].capabilities(), vec![Capability::Pointer]);
+        assert_eq!(
+            devices[2].devnode().as_deref(),
+            Some(std::path::Path::new("/dev/input/event5"))
+        );
+        assert!(devices[3].capabilities().is_empty());
+    }
+}
@@ -0,0 +1,503 @@
+use crate::*;
+
+use serde::Deserialize;
+use std::borrow::Cow;
+
+/// A quirk for a well-known device, applied on top of the information
+/// derived from udev and the kernel. The quirks compiled into whodat are
+/// available via [`Quirk::all`], more quirks can be loaded at runtime for
+/// one [`DeviceTree`], see [`DeviceTree::with_quirks`].
+#[derive(Clone, Debug)]
+pub struct Quirk {
+    vid: u16,
+    pid: u16,
+    /// The product version this quirk is restricted to, typically
+    /// the firmware revision. A quirk without a version applies to all
+    /// versions of the device.
+    version: Option<u16>,
+    /// Capabilities this device has even where udev or the kernel don't say so
+    add: Cow<'static, [Capability]>,
+    /// Capabilities this device does not have even where udev or the kernel say so
+    remove: Cow<'static, [Capability]>,
+    /// The abstract type of this device, overriding the calculated one
+    abstract_type: Option<AbstractType>,
+}
+
+impl Quirk {
+    /// Apply this quirk to the given capabilities. The capabilities forced
+    /// by this quirk are extended with their parent capabilities, see
+    /// [`Capability::extend`].
+    pub(crate) fn apply(&self, capabilities: Vec<Capability>) -> Vec<Capability> {
+        let mut capabilities = capabilities;
+        capabilities.extend(self.add.iter());
+        Capability::extend(capabilities)
+            .into_iter()
+            .filter(|c| !self.remove.contains(c))
+            .collect()
+    }
+
+    /// Load all `*.toml` quirk files in the given directory, in alphabetical
+    /// order. Returns the loaded quirks, the quirks of a later file before
+    /// those of an earlier one, so that a later file takes precedence.
+    ///
+    /// Loading quirks does not change the classification of any device,
+    /// see [`DeviceTree::with_quirks`] to apply them to the devices of a tree.
+    ///
+    /// A malformed quirk file is skipped with a warning, a quirk file
+    /// contains one or more quirks:
+    ///
+    /// ```toml
+    /// # Sony DualSense, add version = 0x0100 to restrict to one firmware
+    /// [[quirk]]
+    /// vid = 0x054c
+    /// pid = 0x0ce6
+    /// add = ["gamepad"]
+    /// remove = ["touchpad"]
+    /// abstract_type = "GamingDevice"
+    /// ```
+    ///
+    /// Capabilities use their canonical names, see [`Capability`]'s
+    /// [`FromStr`](std::str::FromStr) implementation, abstract types use the
+    /// [`AbstractType`] variant names.
+    pub fn load_dir(path: &Path) -> Result<Vec<Quirk>, Error> {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(path)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| p.extension().map_or(false, |ext| ext == "toml"))
+            .collect();
+        files.sort();
+
+        let mut loaded = Vec::new();
+        for file in files {
+            match load_file(&file) {
+                Ok(quirks) => loaded.extend(quirks),
+                Err(e) => log::warn!("Skipping quirk file {file:?}: {e}"),
+            }
+        }
+        // quirk_for() uses the first match, so the most recent quirk goes first
+        loaded.reverse();
+
+        Ok(loaded)
+    }
+
+    /// Returns all quirks compiled into whodat.
+    pub fn all() -> impl Iterator<Item = &'static Quirk> {
+        QUIRKS.iter()
+    }
+
+    /// The USB vendor id of the device this quirk applies to
+    pub fn vid(&self) -> u16 {
+        self.vid
+    }
+
+    /// The USB product id of the device this quirk applies to
+    pub fn pid(&self) -> u16 {
+        self.pid
+    }
+
+    /// The product version this quirk is restricted to or `None` if the
+    /// quirk applies to all versions of the device
+    pub fn version(&self) -> Option<u16> {
+        self.version
+    }
+
+    /// The capabilities this quirk adds to the device
+    pub fn added(&self) -> &[Capability] {
+        &self.add
+    }
+
+    /// The capabilities this quirk removes from the device
+    pub fn removed(&self) -> &[Capability] {
+        &self.remove
+    }
+
+    /// The abstract type of the device, overriding the calculated one, if any
+    pub fn abstract_type(&self) -> Option<AbstractType> {
+        self.abstract_type.clone()
+    }
+}
+
+/// A quirk as written in a quirk file, see [`Quirk::load_dir`]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QuirkEntry {
+    vid: u16,
+    pid: u16,
+    #[serde(default)]
+    version: Option<u16>,
+    #[serde(default)]
+    add: Vec<String>,
+    #[serde(default)]
+    remove: Vec<String>,
+    #[serde(default)]
+    abstract_type: Option<AbstractType>,
+}
+
+#[derive(Deserialize)]
+struct QuirkFile {
+    #[serde(default)]
+    quirk: Vec<QuirkEntry>,
+}
+
+impl TryFrom<QuirkEntry> for Quirk {
+    type Error = String;
+
+    fn try_from(entry: QuirkEntry) -> Result<Quirk, String> {
+        let parse = |names: &[String]| -> Result<Vec<Capability>, String> {
+            names.iter().map(|n| n.parse()).collect()
+        };
+        Ok(Quirk {
+            vid: entry.vid,
+            pid: entry.pid,
+            version: entry.version,
+            add: Cow::Owned(parse(&entry.add)?),
+            remove: Cow::Owned(parse(&entry.remove)?),
+            abstract_type: entry.abstract_type,
+        })
+    }
+}
+
+/// Parse all quirks in the quirk file, failing if any quirk is invalid
+fn load_file(path: &Path) -> Result<Vec<Quirk>, Box<dyn StdError>> {
+    let data = std::fs::read_to_string(path)?;
+    let file: QuirkFile = toml::from_str(&data)?;
+    let quirks = file
+        .quirk
+        .into_iter()
+        .map(Quirk::try_from)
+        .collect::<Result<Vec<Quirk>, String>>()?;
+    Ok(quirks)
+}
+
+/// Returns the quirk for the device with the given vendor and product id
+/// and version, if any. A quirk for the specific version takes precedence over
+/// a quirk for all versions. Where the version is `None`, only quirks
+/// for all versions are considered.
+///
+/// A matching quirk in `external`, the quirks loaded at runtime with
+/// [`Quirk::load_dir`], takes precedence over the compiled-in quirks.
+pub(crate) fn quirk_for(
+    external: &[Quirk],
+    vid: u16,
+    pid: u16,
+    version: Option<u16>,
+) -> Option<Quirk> {
+    find_quirk(external, vid, pid, version).or_else(|| find_quirk(QUIRKS, vid, pid, version))
+}
+
+fn find_quirk(quirks: &[Quirk], vid: u16, pid: u16, version: Option<u16>) -> Option<Quirk> {
+    let quirks = quirks.iter().filter(|q| q.vid == vid && q.pid == pid);
+    quirks
+        .clone()
+        .find(|q| q.version.is_some() && q.version == version)
+        .or_else(|| quirks.clone().find(|q| q.version.is_none()))
+        .cloned()
+}
+
+static QUIRKS: &[Quirk] = &[
+    // Sony DualShock 4
+    Quirk {
+        vid: 0x054c,
+        pid: 0x05c4,
+        version: None,
+        add: Cow::Borrowed(&[Capability::Gamepad]),
+        remove: Cow::Borrowed(&[]),
+        abstract_type: Some(AbstractType::GamingDevice),
+    },
+    // Sony DualShock 4 (2nd gen)
+    Quirk {
+        vid: 0x054c,
+        pid: 0x09cc,
+        version: None,
+        add: Cow::Borrowed(&[Capability::Gamepad]),
+        remove: Cow::Borrowed(&[]),
+        abstract_type: Some(AbstractType::GamingDevice),
+    },
+    // Sony DualSense
+    Quirk {
+        vid: 0x054c,
+        pid: 0x0ce6,
+        version: None,
+        add: Cow::Borrowed(&[Capability::Gamepad]),
+        remove: Cow::Borrowed(&[]),
+        abstract_type: Some(AbstractType::GamingDevice),
+    },
+    // Nintendo Switch Pro Controller
+    Quirk {
+        vid: 0x057e,
+        pid: 0x2009,
+        version: None,
+        add: Cow::Borrowed(&[Capability::Gamepad]),
+        remove: Cow::Borrowed(&[]),
+        abstract_type: Some(AbstractType::GamingDevice),
+    },
+    // Gaming mice with a keyboard interface for their macro buttons
+    // Logitech G Pro Wireless
+    Quirk {
+        vid: 0x046d,
+        pid: 0xc088,
+        version: None,
+        add: Cow::Borrowed(&[]),
+        remove: Cow::Borrowed(&[]),
+        abstract_type: Some(AbstractType::Pointer),
+    },
+    // Logitech G502 HERO
+    Quirk {
+        vid: 0x046d,
+        pid: 0xc08b,
+        version: None,
+        add: Cow::Borrowed(&[]),
+        remove: Cow::Borrowed(&[]),
+        abstract_type: Some(AbstractType::Pointer),
+    },
+    // Razer DeathAdder V2
+    Quirk {
+        vid: 0x1532,
+        pid: 0x0084,
+        version: None,
+        add: Cow::Borrowed(&[]),
+        remove: Cow::Borrowed(&[]),
+        abstract_type: Some(AbstractType::Pointer),
+    },
+    // SteelSeries Rival 3
+    Quirk {
+        vid: 0x1038,
+        pid: 0x1824,
+        version: None,
+        add: Cow::Borrowed(&[]),
+        remove: Cow::Borrowed(&[]),
+        abstract_type: Some(AbstractType::Pointer),
+    },
+    // Force Touch trackpads, the kernel only knows them as clickpads
+    // Apple Magic Trackpad 2 (USB)
+    Quirk {
+        vid: 0x05ac,
+        pid: 0x0265,
+        version: None,
+        add: Cow::Borrowed(&[Capability::Pressurepad]),
+        remove: Cow::Borrowed(&[]),
+        abstract_type: Some(AbstractType::Pointer),
+    },
+    // Apple Magic Trackpad 2 (Bluetooth)
+    Quirk {
+        vid: 0x004c,
+        pid: 0x0265,
+        version: None,
+        add: Cow::Borrowed(&[Capability::Pressurepad]),
+        remove: Cow::Borrowed(&[]),
+        abstract_type: Some(AbstractType::Pointer),
+    },
+    // Apple Magic Trackpad USB-C (USB)
+    Quirk {
+        vid: 0x05ac,
+        pid: 0x0324,
+        version: None,
+        add: Cow::Borrowed(&[Capability::Pressurepad]),
+        remove: Cow::Borrowed(&[]),
+        abstract_type: Some(AbstractType::Pointer),
+    },
+    // Apple Magic Trackpad USB-C (Bluetooth)
+    Quirk {
+        vid: 0x004c,
+        pid: 0x0324,
+        version: None,
+        add: Cow::Borrowed(&[Capability::Pressurepad]),
+        remove: Cow::Borrowed(&[]),
+        abstract_type: Some(AbstractType::Pointer),
+    },
+    // Trackballs, the kernel and udev cannot tell them from a mouse
+    // Logitech Trackman Marble
+    Quirk {
+        vid: 0x046d,
+        pid: 0xc408,
+        version: None,
+        add: Cow::Borrowed(&[Capability::Trackball]),
+        remove: Cow::Borrowed(&[]),
+        abstract_type: Some(AbstractType::Pointer),
+    },
+    // Kensington Expert Mouse
+    Quirk {
+        vid: 0x047d,
+        pid: 0x1020,
+        version: None,
+        add: Cow::Borrowed(&[Capability::Trackball]),
+        remove: Cow::Borrowed(&[]),
+        abstract_type: Some(AbstractType::Pointer),
+    },
+    // Kensington SlimBlade
+    Quirk {
+        vid: 0x047d,
+        pid: 0x2041,
+        version: None,
+        add: Cow::Borrowed(&[Capability::Trackball]),
+        remove: Cow::Borrowed(&[]),
+        abstract_type: Some(AbstractType::Pointer),
+    },
+    // ELECOM HUGE
+    Quirk {
+        vid: 0x056e,
+        pid: 0x010c,
+        version: None,
+        add: Cow::Borrowed(&[Capability::Trackball]),
+        remove: Cow::Borrowed(&[]),
+        abstract_type: Some(AbstractType::Pointer),
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quirk_for() {
+        // Sony DualSense
+        let quirk = quirk_for(&[], 0x054c, 0x0ce6, Some(0x8111)).unwrap();
+        assert_eq!(quirk.added(), &[Capability::Gamepad]);
+        assert!(matches!(
+            quirk.abstract_type(),
+            Some(AbstractType::GamingDevice)
+        ));
+        assert!(quirk_for(&[], 0x054c, 0x0ce6, None).is_some());
+
+        assert!(quirk_for(&[], 0x054c, 0xffff, None).is_none());
+    }
+
+    #[test]
+    fn test_quirk_apply() {
+        let quirk = Quirk {
+            vid: 0x1234,
+            pid: 0x5678,
+            version: None,
+            add: Cow::Borrowed(&[Capability::Trackball]),
+            remove: Cow::Borrowed(&[Capability::Keyboard]),
+            abstract_type: None,
+        };
+        let mut caps = quirk.apply(vec![Capability::Keyboard, Capability::Pointer]);
+        caps.sort();
+        // Trackball implies Pointer, see Capability::extend
+        assert_eq!(caps, vec![Capability::Pointer, Capability::Trackball]);
+    }
+
+    #[test]
+    fn test_quirk_changes_device() {
+        let udev_properties = &["ID_INPUT_JOYSTICK"];
+        let dualsense = EvdevDevice::from_parts(
+            "Sony Interactive Entertainment DualSense Wireless Controller",
+            0x054c,
+            0x0ce6,
+            udev_properties,
+            &[],
+            None,
+        );
+        let other = EvdevDevice::from_parts(
+            "Generic USB Joystick",
+            0x0079,
+            0x0006,
+            udev_properties,
+            &[],
+            None,
+        );
+        assert!(dualsense.has(Capability::Gamepad));
+        assert!(dualsense.sources().contains(&Source::Quirk));
+        assert!(!other.has(Capability::Gamepad));
+        assert!(!other.sources().contains(&Source::Quirk));
+
+        let mut tree = DeviceTree::new();
+        let idx = tree.attach_evdev_device(dualsense);
+        let pidx = tree.get_evdev_device(&idx).unwrap().parent();
+        let parent = tree.get_parent_device(&pidx).unwrap();
+        assert!(matches!(
+            parent.abstract_types()[..],
+            [AbstractType::GamingDevice]
+        ));
+    }
+
+    #[test]
+    fn test_trackball() {
+        use Capability::*;
+
+        // Logitech Trackman Marble, udev says it's a mouse and the name
+        // doesn't give it away
+        let trackball = EvdevDevice::from_parts(
+            "Logitech USB Receiver",
+            0x046d,
+            0xc408,
+            &["ID_INPUT_MOUSE"],
+            &[],
+            None,
+        );
+        assert_eq!(trackball.capabilities(), vec![Pointer, Trackball]);
+        assert_eq!(
+            trackball
+                .capability_sources()
+                .iter()
+                .find(|(c, _)| *c == Trackball),
+            Some(&(Trackball, Source::Quirk))
+        );
+
+        let mut parent = PhysicalDevice::new();
+        parent.merge_capabilities(&AttachedDevice::Evdev(trackball));
+        assert_eq!(parent.device_type(), Some(DeviceType::Trackball));
+
+        // Not quirked, only the name tells us
+        let trackball = EvdevDevice::from_parts(
+            "Generic Trackball",
+            0x1234,
+            0x5678,
+            &["ID_INPUT_MOUSE"],
+            &[],
+            None,
+        );
+        assert_eq!(trackball.capabilities(), vec![Pointer, Trackball]);
+        assert_eq!(
+            trackball
+                .capability_sources()
+                .iter()
+                .find(|(c, _)| *c == Trackball),
+            Some(&(Trackball, Source::Name))
+        );
+    }
+
+    #[test]
+    fn test_load_dir() {
+        let dir = std::env::temp_dir().join(format!("whodat-quirks-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // A Logitech K400, primarily a keyboard even though it has a touchpad
+        let k400 = "[[quirk]]\nvid = 0x046d\npid = 0x404d\nabstract_type = \"Keyboard\"\n";
+        std::fs::write(dir.join("50-k400.toml"), k400).unwrap();
+        std::fs::write(dir.join("60-broken.toml"), "[[quirk]\nvid = ").unwrap();
+        let unknown = "[[quirk]]\nvid = 0x1234\npid = 0x5678\nadd = [\"flying\"]\n";
+        std::fs::write(dir.join("70-unknown.toml"), unknown).unwrap();
+        std::fs::write(dir.join("README"), "not a quirk file").unwrap();
+
+        let quirks = Quirk::load_dir(&dir).unwrap();
+        assert_eq!(quirks.len(), 1);
+        assert_eq!((quirks[0].vid(), quirks[0].pid()), (0x046d, 0x404d));
+
+        let abstract_type = |tree: &mut DeviceTree| {
+            let mut pidx = None;
+            for (name, prop) in [
+                ("Logitech K400", "ID_INPUT_KEYBOARD"),
+                ("Logitech K400 Touchpad", "ID_INPUT_TOUCHPAD"),
+            ] {
+                let mut device = EvdevDevice::from_parts(name, 0x046d, 0x404d, &[prop], &[], None);
+                device.set_phys("usb-0000:00:14.0-2/input2");
+                let idx = tree.attach_evdev_device(device);
+                pidx = Some(tree.get_evdev_device(&idx).unwrap().parent());
+            }
+            let parent = tree.get_parent_device(&pidx.unwrap()).unwrap();
+            parent.abstract_types().first().cloned()
+        };
+        let mut quirked = DeviceTree::new().with_quirks(&dir);
+        assert!(matches!(
+            abstract_type(&mut quirked),
+            Some(AbstractType::Keyboard)
+        ));
+        // The quirks only apply to the tree they were loaded for
+        assert!(matches!(
+            abstract_type(&mut DeviceTree::new()),
+            Some(AbstractType::Pointer)
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,56 @@
+use crate::{AttachedDevice, DeviceIndex};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::error::Error as StdError;
+
+/// The version of the serialization format. Bump this whenever the
+/// serialized representation of a device changes in an incompatible way.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The versioned wrapper around every serialized device
+#[derive(Serialize, Deserialize)]
+struct Envelope<T> {
+    version: u32,
+    device: T,
+}
+
+/// Only the version of an [`Envelope`] so we can reject a payload
+/// before attempting to parse its contents.
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+/// A single device in a serialized [`crate::DeviceTree`], including
+/// its attachment to the tree.
+/// `D` is either a `&AttachedDevice` for serialization or an
+/// [`AttachedDevice`] for deserialization.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct TreeEntry<D> {
+    pub(crate) index: DeviceIndex,
+    pub(crate) parent: Option<DeviceIndex>,
+    pub(crate) device: D,
+}
+
+/// Serialize the given device into the versioned JSON format.
+pub(crate) fn to_string<T: Serialize>(device: &T) -> String {
+    let envelope = Envelope {
+        version: FORMAT_VERSION,
+        device,
+    };
+    serde_json::to_string(&envelope).expect("Failed to serialize device")
+}
+
+/// Deserialize a device from the versioned JSON format, rejecting
+/// any payload with a format version other than [`FORMAT_VERSION`].
+pub(crate) fn from_str<T: DeserializeOwned>(data: &str) -> Result<T, Box<dyn StdError>> {
+    let header: Header = serde_json::from_str(data)?;
+    if header.version != FORMAT_VERSION {
+        return Err(format!(
+            "Unsupported serialization format version {}, expected {}",
+            header.version, FORMAT_VERSION
+        )
+        .into());
+    }
+    let envelope: Envelope<T> = serde_json::from_str(data)?;
+    Ok(envelope.device)
+}
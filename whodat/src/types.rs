@@ -1,3 +1,7 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
 /// A high-level category describing a capability on this device.
 /// Capabilities are not mutually exclusive (some are, see the documentation for
 /// each capability) and any device may match one or more of those capabilities.
@@ -10,8 +14,12 @@
 /// accordingly. For example, a caller expecting a mouse should check
 /// that the [`Capability::Pointer`] is present but the
 /// [`Capability::Touchpad`] (amongst others) is not present.
+///
+/// Capabilities are ordered in the order they are declared here. This order
+/// is stable, all functions returning multiple capabilities return them
+/// sorted in this order.
 #[non_exhaustive]
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
 pub enum Capability {
     Keyboard,
     Pointer,
@@ -21,11 +29,21 @@ pub enum Capability {
     Clickpad,
     /// A touchpad without physical buttons that uses physical pressure to detect button
     /// presses instead of e.g. a mechanical hinge.
+    ///
+    /// The kernel cannot tell a pressurepad from a [`Capability::Clickpad`], this
+    /// capability is only set for devices in the internal database of well-known devices.
     Pressurepad,
     Touchscreen,
+    /// A trackball. The kernel does not distinguish a trackball from a mouse
+    /// and udev rarely tags trackballs, so this capability is primarily set for
+    /// devices in the internal database of well-known devices or where the
+    /// device's name says it is a trackball.
     Trackball,
     Joystick,
     Gamepad,
+    /// A racing wheel, typically with pedals. udev does not tag racing wheels,
+    /// so this capability can not be derived from udev properties alone.
+    RacingWheel,
     Tablet,
     /// A tablet built into a screen, e.g. like the Wacom Cintiq series.
     /// This capability is mutually exclusive with the [`Capability::TabletExternal`] capability.
@@ -37,26 +55,80 @@ pub enum Capability {
     /// on many [`Capability::Tablet`] devices.
     TabletPad,
     Switch,
+    /// This device supports a pen or stylus, e.g. the pen of a graphics tablet or the
+    /// active stylus of a touchscreen. This capability does not imply
+    /// [`Capability::Tablet`], a touchscreen with stylus support is still a touchscreen.
+    Stylus,
+    /// A rotary dial or knob, e.g. like the Microsoft Surface Dial or a volume knob.
+    /// A dial is not a [`Capability::Pointer`].
+    Dial,
+    /// An accelerometer or gyroscope, e.g. the orientation sensor of a
+    /// laptop or the motion sensor of a gaming controller.
+    Accelerometer,
+    /// A standalone numeric keypad, i.e. a device with the keypad keys but
+    /// without the alphanumeric keys of a keyboard. A numpad is also a
+    /// [`Capability::Keyboard`], a keyboard with a keypad is not a numpad.
+    Numpad,
 }
 
 impl Capability {
-    /// Returns the *single* capability that matches to the udev property, if any.
-    pub(crate) fn from_udev_prop(name: &str) -> Option<Self> {
-        let cap = match name {
-            "ID_INPUT_KEY" => Capability::Keyboard,
-            "ID_INPUT_KEYBOARD" => Capability::Keyboard,
-            "ID_INPUT_MOUSE" => Capability::Pointer,
-            "ID_INPUT_TOUCHPAD" => Capability::Touchpad,
-            "ID_INPUT_TOUCHSCREEN" => Capability::Touchscreen,
-            "ID_INPUT_TRACKBALL" => Capability::Trackball,
-            "ID_INPUT_POINTINGSTICK" => Capability::Pointingstick,
-            "ID_INPUT_TABLET" => Capability::Tablet,
-            "ID_INPUT_TABLET_PAD" => Capability::TabletPad,
-            "ID_INPUT_TABLET_JOYSTICK" => Capability::Joystick,
-            "ID_INPUT_SWITCH" => Capability::Switch,
-            _ => return None,
-        };
-        Some(cap)
+    /// Returns the capabilities that match the udev property, if any. Most
+    /// properties match a single capability.
+    pub(crate) fn from_udev_prop(name: &str) -> &'static [Self] {
+        match name {
+            "ID_INPUT_KEY" => &[Capability::Keyboard],
+            "ID_INPUT_KEYBOARD" => &[Capability::Keyboard],
+            "ID_INPUT_MOUSE" => &[Capability::Pointer],
+            "ID_INPUT_TOUCHPAD" => &[Capability::Touchpad],
+            "ID_INPUT_TOUCHSCREEN" => &[Capability::Touchscreen],
+            "ID_INPUT_TRACKBALL" => &[Capability::Trackball],
+            "ID_INPUT_POINTINGSTICK" => &[Capability::Pointingstick],
+            "ID_INPUT_TABLET" => &[Capability::Tablet],
+            "ID_INPUT_TABLET_PAD" => &[Capability::TabletPad],
+            "ID_INPUT_JOYSTICK" => &[Capability::Joystick],
+            // A tablet with a joystick, e.g. some older Wacom art pads. This is
+            // a tablet first, not a gaming device, see ABSTRACT_TYPE_PRIORITY
+            "ID_INPUT_TABLET_JOYSTICK" => &[Capability::Tablet, Capability::Joystick],
+            "ID_INPUT_SWITCH" => &[Capability::Switch],
+            "ID_INPUT_ACCELEROMETER" => &[Capability::Accelerometer],
+            _ => &[],
+        }
+    }
+
+    /// Returns the capabilities guessed from the device name, if any.
+    /// The match is case-insensitive and based on common substrings, e.g. the
+    /// HID application suffixes the kernel appends to the device name.
+    pub(crate) fn from_name(name: &str) -> Vec<Self> {
+        let name = name.to_lowercase();
+        let mut caps = Vec::new();
+        if name.contains("mouse") {
+            caps.push(Capability::Pointer);
+        }
+        if name.contains("touchpad") {
+            caps.push(Capability::Touchpad);
+        }
+        if name.contains("trackball") || name.contains("trackman") {
+            caps.push(Capability::Trackball);
+        }
+        // e.g. "TPPS/2 IBM TrackPoint" or "TPPS/2 Elan TrackPoint"
+        if name.contains("trackpoint") || name.contains("pointing stick") {
+            caps.push(Capability::Pointingstick);
+        }
+        if name.contains("keyboard")
+            || name.contains("consumer control")
+            || name.contains("system control")
+        {
+            caps.push(Capability::Keyboard);
+        }
+        // "Mouse Wheel" is not a racing wheel
+        if name.contains("wheel") && !name.contains("mouse") {
+            caps.push(Capability::RacingWheel);
+        }
+        if name.contains("stylus") {
+            caps.push(Capability::Tablet);
+            caps.push(Capability::Stylus);
+        }
+        caps
     }
 
     /// Create a new vector of capabilities that extend the given
@@ -73,14 +145,168 @@ impl Capability {
         if caps.has(Capability::Clickpad) || caps.has(Capability::Pressurepad) {
             caps.set(Capability::Touchpad);
         }
-        if caps.has(Capability::Touchpad) {
+        if caps.has(Capability::Touchpad)
+            || caps.has(Capability::Pointingstick)
+            || caps.has(Capability::Trackball)
+        {
             caps.set(Capability::Pointer);
         }
-
-        // FIXME: need more settings here
+        if caps.has(Capability::TabletScreen) || caps.has(Capability::TabletExternal) {
+            caps.set(Capability::Tablet);
+        }
+        if caps.has(Capability::Numpad) {
+            caps.set(Capability::Keyboard);
+        }
+        // Gaming devices often have a mouse-like stick but that doesn't
+        // make them a pointer device, so Joystick and Gamepad imply nothing.
+        // Likewise, a Stylus may be on a tablet or a touchscreen.
 
         let caps = caps.to_vec();
-        caps
+        log::trace!("Extended capabilities to {caps:?}");
+        Capability::validate(caps)
+    }
+
+    /// Returns true if this capability is mutually exclusive with the other
+    /// capability, i.e. a single kernel device cannot have both.
+    pub fn conflicts_with(&self, other: Capability) -> bool {
+        matches!(
+            (self, other),
+            (Capability::TabletScreen, Capability::TabletExternal)
+                | (Capability::TabletExternal, Capability::TabletScreen)
+                | (Capability::Touchscreen, Capability::TabletExternal)
+                | (Capability::TabletExternal, Capability::Touchscreen)
+        )
+    }
+
+    /// Resolve any conflicting capabilities (see [`Capability::conflicts_with`]),
+    /// keeping the more specific capability where there is one.
+    pub(crate) fn validate(capabilities: Vec<Capability>) -> Vec<Capability> {
+        let mut caps = Cap::new(capabilities);
+        // Neither is more specific, all we know is that it's a tablet
+        if caps.has(Capability::TabletScreen) && caps.has(Capability::TabletExternal) {
+            log::warn!("Device is both a screen and an external tablet, dropping both");
+            caps.unset(Capability::TabletScreen);
+            caps.unset(Capability::TabletExternal);
+            caps.set(Capability::Tablet);
+        }
+        // An external tablet is more specific than a touchscreen
+        if caps.has(Capability::Touchscreen) && caps.has(Capability::TabletExternal) {
+            log::warn!("Device is both a touchscreen and an external tablet, dropping touchscreen");
+            caps.unset(Capability::Touchscreen);
+        }
+        caps.to_vec()
+    }
+}
+
+/// All capabilities, in the order of their declaration
+const ALL_CAPABILITIES: &[Capability] = &[
+    Capability::Keyboard,
+    Capability::Pointer,
+    Capability::Pointingstick,
+    Capability::Touchpad,
+    Capability::Clickpad,
+    Capability::Pressurepad,
+    Capability::Touchscreen,
+    Capability::Trackball,
+    Capability::Joystick,
+    Capability::Gamepad,
+    Capability::RacingWheel,
+    Capability::Tablet,
+    Capability::TabletScreen,
+    Capability::TabletExternal,
+    Capability::TabletPad,
+    Capability::Switch,
+    Capability::Stylus,
+    Capability::Dial,
+    Capability::Accelerometer,
+    Capability::Numpad,
+];
+
+impl Capability {
+    /// Returns all capabilities, in the order of their declaration.
+    ///
+    /// [`Capability`] is `#[non_exhaustive]`, use this function to enumerate
+    /// all capabilities known to this version of whodat.
+    pub fn all() -> &'static [Capability] {
+        ALL_CAPABILITIES
+    }
+
+    /// The bit of this capability in a capability bitmask, e.g. as returned
+    /// by the C API. The bit of a capability never changes, new capabilities
+    /// are assigned new bits.
+    pub fn as_bit(&self) -> u32 {
+        Cap::as_mask(*self)
+    }
+
+    /// The udev `ID_INPUT_*` property for this capability, e.g.
+    /// `"ID_INPUT_TOUCHPAD"`, or `None` where udev does not tag this
+    /// capability, e.g. [`Capability::Clickpad`].
+    pub fn udev_property(&self) -> Option<&'static str> {
+        match self {
+            Capability::Keyboard => Some("ID_INPUT_KEYBOARD"),
+            Capability::Pointer => Some("ID_INPUT_MOUSE"),
+            Capability::Pointingstick => Some("ID_INPUT_POINTINGSTICK"),
+            Capability::Touchpad => Some("ID_INPUT_TOUCHPAD"),
+            Capability::Touchscreen => Some("ID_INPUT_TOUCHSCREEN"),
+            Capability::Trackball => Some("ID_INPUT_TRACKBALL"),
+            Capability::Joystick => Some("ID_INPUT_JOYSTICK"),
+            Capability::Tablet => Some("ID_INPUT_TABLET"),
+            Capability::TabletPad => Some("ID_INPUT_TABLET_PAD"),
+            Capability::Switch => Some("ID_INPUT_SWITCH"),
+            Capability::Accelerometer => Some("ID_INPUT_ACCELEROMETER"),
+            _ => None,
+        }
+    }
+
+    /// The canonical lowercase name of this capability, e.g. `"tablet-pad"`.
+    fn as_str(&self) -> &'static str {
+        match self {
+            Capability::Keyboard => "keyboard",
+            Capability::Pointer => "pointer",
+            Capability::Pointingstick => "pointingstick",
+            Capability::Touchpad => "touchpad",
+            Capability::Clickpad => "clickpad",
+            Capability::Pressurepad => "pressurepad",
+            Capability::Touchscreen => "touchscreen",
+            Capability::Trackball => "trackball",
+            Capability::Joystick => "joystick",
+            Capability::Gamepad => "gamepad",
+            Capability::RacingWheel => "racing-wheel",
+            Capability::Tablet => "tablet",
+            Capability::TabletScreen => "tablet-screen",
+            Capability::TabletExternal => "tablet-external",
+            Capability::TabletPad => "tablet-pad",
+            Capability::Switch => "switch",
+            Capability::Stylus => "stylus",
+            Capability::Dial => "dial",
+            Capability::Accelerometer => "accelerometer",
+            Capability::Numpad => "numpad",
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    /// Formats the capability as its canonical lowercase name, the
+    /// reverse of [`Capability::from_str`].
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Capability {
+    type Err = String;
+
+    /// Parse a capability from its canonical lowercase name, e.g. `"touchpad"`
+    /// or `"tablet-pad"`, see the [`Display`](fmt::Display) implementation.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Capability::all()
+            .iter()
+            .find(|c| c.as_str() == s)
+            .copied()
+            .ok_or_else(|| {
+                let valid: Vec<&str> = Capability::all().iter().map(|c| c.as_str()).collect();
+                format!("Unknown capability '{s}', expected one of: {}", valid.join(", "))
+            })
     }
 }
 
@@ -91,9 +317,10 @@ impl Capability {
 /// (e.g. many gaming mice can send key events) but this represents the most obvious
 /// category for this device.
 #[non_exhaustive]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum AbstractType {
-    /// Device is primarily a keyboard
+    /// Device is primarily a keyboard. This includes devices that only
+    /// have media or consumer control keys.
     Keyboard,
     /// Device is primarily a pointer device, e.g. a mouse, touchpad, or pointingstick
     Pointer,
@@ -103,21 +330,27 @@ pub enum AbstractType {
     Tablet,
     /// Device is primarily a gaming device, e.g. a joystick, gamepad or racing wheel
     GamingDevice,
-    /// Device is primarily a switch toggle
+    /// Device is primarily a switch toggle, e.g. a lid switch. A device with
+    /// keys and switches is a [`AbstractType::Keyboard`].
     Switch,
+    /// Device is primarily a rotary dial or knob
+    Dial,
+    /// Device is primarily a motion or orientation sensor, e.g. the
+    /// accelerometer of a laptop
+    Sensor,
 }
 
-/// Describes the **physical** type of this device. Unlike the [`Device::has_capability`]
-/// a device may only have one physical type. For example, modern PlayStation controllers
-/// provide a touchpad as well as a gamepad - the physical type of this controller however
-/// is [`AbstractType::GamingDevice`].
+/// Describes the **physical** type of this device. Unlike the capabilities (see
+/// [`HasCapability`](crate::HasCapability)) a device may only have one physical type.
+/// For example, modern PlayStation controllers provide a touchpad as well as a
+/// gamepad - the physical type of this controller however is [`DeviceType::Gamepad`].
 ///
 /// The physical type of the device may not always be known, especially if the device
-/// is constructed from a single event node via [`Builder::evdev_fd`]. This crate may
-/// rely on an internal database for well-known devices to supplement the information
-/// where posssible.
+/// is constructed from a single event node. This crate may rely on an internal database
+/// for well-known devices to supplement the information where posssible, see
+/// [`PhysicalDevice::device_type`](crate::PhysicalDevice::device_type).
 #[non_exhaustive]
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum DeviceType {
     Keyboard,
     Mouse,
@@ -130,9 +363,309 @@ pub enum DeviceType {
     Gamepad,
     RacingWheel,
     FootPedal,
+    Dial,
+    Numpad,
+}
+
+/// The source of the information used to classify a device, in increasing
+/// order of reliability.
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Source {
+    /// Guessed from the device name
+    Name,
+    /// Derived from the udev `ID_INPUT_*` properties
+    Udev,
+    /// Derived from the kernel device, e.g. the `INPUT_PROP_*` properties
+    Kernel,
+    /// Taken from the internal database of well-known devices
+    Quirk,
+}
+
+impl Source {
+    /// The confidence in a classification based on this source, in the range `[0.0, 1.0]`
+    pub(crate) fn confidence(&self) -> f32 {
+        match self {
+            Source::Name => 0.3,
+            Source::Udev => 0.9,
+            Source::Kernel => 0.9,
+            Source::Quirk => 1.0,
+        }
+    }
+}
+
+/// The range and resolution of an absolute axis as reported by the kernel's
+/// `EVIOCGABS`, see
+/// [`EvdevDevice::abs_resolution`](crate::EvdevDevice::abs_resolution).
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AbsResolution {
+    /// The minimum value of the axis
+    pub minimum: i32,
+    /// The maximum value of the axis
+    pub maximum: i32,
+    /// The resolution in units per mm, or units per radian for rotational
+    /// axes. Zero if the kernel does not know the resolution.
+    pub resolution: i32,
+    /// The fuzz value used to filter noise
+    pub fuzz: i32,
+    /// The flat value, i.e. the axis' dead zone
+    pub flat: i32,
+}
+
+/// The button and axis inventory of a gaming device, see
+/// [`EvdevDevice::gamepad_profile`](crate::EvdevDevice::gamepad_profile).
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct GamepadProfile {
+    /// The number of buttons in the `BTN_JOYSTICK` and `BTN_GAMEPAD` ranges
+    pub buttons: usize,
+    /// The number of analog sticks, i.e. `ABS_X`/`ABS_Y` and `ABS_RX`/`ABS_RY` pairs
+    pub sticks: usize,
+    /// True if the device has a hat switch (`ABS_HAT0X` and `ABS_HAT0Y`)
+    pub has_hat: bool,
+    /// True if the device has analog triggers (`ABS_Z` and `ABS_RZ`)
+    pub has_triggers: bool,
+    /// True if the device has pedals (`ABS_GAS`, `ABS_BRAKE` or `ABS_THROTTLE`)
+    pub has_pedals: bool,
+}
+
+impl GamepadProfile {
+    const ABS_X: u16 = 0x00;
+    const ABS_Y: u16 = 0x01;
+    const ABS_Z: u16 = 0x02;
+    const ABS_RX: u16 = 0x03;
+    const ABS_RY: u16 = 0x04;
+    const ABS_RZ: u16 = 0x05;
+    const ABS_THROTTLE: u16 = 0x06;
+    const ABS_GAS: u16 = 0x09;
+    const ABS_BRAKE: u16 = 0x0a;
+    const ABS_HAT0X: u16 = 0x10;
+    const ABS_HAT0Y: u16 = 0x11;
+    /// `BTN_TRIGGER` to `BTN_DEAD`
+    const BTN_JOYSTICK: std::ops::RangeInclusive<u16> = 0x120..=0x12f;
+    /// `BTN_SOUTH` to `BTN_THUMBR`
+    const BTN_GAMEPAD: std::ops::RangeInclusive<u16> = 0x130..=0x13e;
+
+    /// Build the profile from the supported `EV_KEY` and `EV_ABS` codes.
+    /// Returns `None` if the codes do not look like a gaming device.
+    pub(crate) fn from_codes(keys: &[u16], abs_axes: &[u16]) -> Option<GamepadProfile> {
+        let has = |code: u16| abs_axes.contains(&code);
+        let buttons = keys
+            .iter()
+            .filter(|k| Self::BTN_JOYSTICK.contains(*k) || Self::BTN_GAMEPAD.contains(*k))
+            .count();
+        let has_pedals = has(Self::ABS_GAS) || has(Self::ABS_BRAKE) || has(Self::ABS_THROTTLE);
+        if buttons == 0 && !has_pedals {
+            return None;
+        }
+
+        let sticks = [(Self::ABS_X, Self::ABS_Y), (Self::ABS_RX, Self::ABS_RY)]
+            .iter()
+            .filter(|(x, y)| has(*x) && has(*y))
+            .count();
+
+        Some(GamepadProfile {
+            buttons,
+            sticks,
+            has_hat: has(Self::ABS_HAT0X) && has(Self::ABS_HAT0Y),
+            has_triggers: has(Self::ABS_Z) && has(Self::ABS_RZ),
+            has_pedals,
+        })
+    }
+
+    /// True if this looks like a racing wheel: a single dominant steering
+    /// axis plus pedals and no second stick.
+    pub fn is_wheel_like(&self) -> bool {
+        self.has_pedals && self.sticks < 2
+    }
+
+    /// True if this looks like a twin-stick gamepad.
+    pub fn is_gamepad_like(&self) -> bool {
+        self.sticks == 2 && self.buttons >= 4
+    }
+}
+
+/// The buttons, rings and strips of a tablet pad, see
+/// [`EvdevDevice::tablet_pad_layout`](crate::EvdevDevice::tablet_pad_layout).
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TabletPadLayout {
+    /// The number of buttons in the `BTN_0`, `BTN_JOYSTICK`/`BTN_GAMEPAD`
+    /// and `BTN_TRIGGER_HAPPY` ranges
+    pub buttons: usize,
+    /// The number of rings, i.e. `ABS_WHEEL` and `ABS_THROTTLE`
+    pub rings: usize,
+    /// The number of strips, i.e. `ABS_RX` and `ABS_RY`
+    pub strips: usize,
+}
+
+impl TabletPadLayout {
+    const ABS_RX: u16 = 0x03;
+    const ABS_RY: u16 = 0x04;
+    const ABS_THROTTLE: u16 = 0x06;
+    const ABS_WHEEL: u16 = 0x08;
+    /// `BTN_0` to `BTN_9` and the unnamed codes up to `BTN_MOUSE`
+    const BTN_MISC: std::ops::Range<u16> = 0x100..0x110;
+    /// `BTN_TRIGGER` to `BTN_THUMBR` and the unnamed codes up to `BTN_DIGI`
+    const BTN_JOYSTICK: std::ops::Range<u16> = 0x120..0x140;
+    /// `BTN_TRIGGER_HAPPY1` to `BTN_TRIGGER_HAPPY40`
+    const BTN_TRIGGER_HAPPY: std::ops::Range<u16> = 0x2c0..0x2e8;
+
+    /// Build the layout from the supported `EV_KEY` and `EV_ABS` codes. The
+    /// button ranges are the ones the kernel uses for tablet pad buttons.
+    pub(crate) fn from_codes(keys: &[u16], abs_axes: &[u16]) -> TabletPadLayout {
+        let count = |codes: &[u16]| codes.iter().filter(|c| abs_axes.contains(c)).count();
+        let buttons = keys
+            .iter()
+            .filter(|k| {
+                Self::BTN_MISC.contains(*k)
+                    || Self::BTN_JOYSTICK.contains(*k)
+                    || Self::BTN_TRIGGER_HAPPY.contains(*k)
+            })
+            .count();
+
+        TabletPadLayout {
+            buttons,
+            rings: count(&[Self::ABS_WHEEL, Self::ABS_THROTTLE]),
+            strips: count(&[Self::ABS_RX, Self::ABS_RY]),
+        }
+    }
+}
+
+/// The bus a device is connected through.
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum BusType {
+    Usb,
+    Bluetooth,
+    I2c,
+    Serial,
+    /// Any bus type not listed above, e.g. virtual devices
+    Unknown,
+}
+
+impl BusType {
+    /// Returns the bus type for the kernel's `BUS_*` value as used in `struct input_id`
+    pub(crate) fn from_raw(bustype: u16) -> Self {
+        match bustype {
+            0x03 => BusType::Usb,
+            0x05 => BusType::Bluetooth,
+            0x13 => BusType::Serial,
+            0x18 => BusType::I2c,
+            _ => BusType::Unknown,
+        }
+    }
+}
+
+/// The kernel's `INPUT_PROP_*` device properties, see
+/// `linux/input-event-codes.h` for details on each property.
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum InputProp {
+    /// `INPUT_PROP_POINTER`: the device needs an on-screen pointer
+    Pointer,
+    /// `INPUT_PROP_DIRECT`: the device maps directly to the screen, e.g. a touchscreen
+    Direct,
+    /// `INPUT_PROP_BUTTONPAD`: the touchpad has its buttons integrated beneath the surface
+    Buttonpad,
+    /// `INPUT_PROP_SEMI_MT`: the touchpad only reports a bounding box of the touches
+    SemiMt,
+    /// `INPUT_PROP_TOPBUTTONPAD`: the touchpad has software buttons along its top edge
+    TopButtonpad,
+    /// `INPUT_PROP_POINTING_STICK`: the device is a pointing stick
+    PointingStick,
+    /// `INPUT_PROP_ACCELEROMETER`: the device is an accelerometer
+    Accelerometer,
+}
+
+impl InputProp {
+    /// Returns the property for the kernel's `INPUT_PROP_*` value, if any
+    pub(crate) fn from_raw(prop: u16) -> Option<Self> {
+        let p = match prop {
+            0x00 => InputProp::Pointer,
+            0x01 => InputProp::Direct,
+            0x02 => InputProp::Buttonpad,
+            0x03 => InputProp::SemiMt,
+            0x04 => InputProp::TopButtonpad,
+            0x05 => InputProp::PointingStick,
+            0x06 => InputProp::Accelerometer,
+            _ => return None,
+        };
+        Some(p)
+    }
+}
+
+/// The kernel's `SW_*` switches, see `linux/input-event-codes.h` for
+/// details on each switch.
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum SwitchType {
+    /// `SW_LID`: a laptop lid
+    Lid,
+    /// `SW_TABLET_MODE`: a convertible's tablet mode
+    TabletMode,
+    /// `SW_HEADPHONE_INSERT`: a headphone jack
+    HeadphoneInsert,
+    /// `SW_RFKILL_ALL`: a radio kill switch
+    RfkillAll,
+    /// `SW_MICROPHONE_INSERT`: a microphone jack
+    MicrophoneInsert,
+    /// `SW_DOCK`: a docking station
+    Dock,
+    /// `SW_LINEOUT_INSERT`: a line out jack
+    LineoutInsert,
+    /// `SW_JACK_PHYSICAL_INSERT`: a jack with mechanical insertion detection
+    JackPhysicalInsert,
+    /// `SW_VIDEOOUT_INSERT`: a video out connector
+    VideooutInsert,
+    /// `SW_CAMERA_LENS_COVER`: a camera lens cover
+    CameraLensCover,
+    /// `SW_KEYPAD_SLIDE`: a slide-out keypad
+    KeypadSlide,
+    /// `SW_FRONT_PROXIMITY`: a front proximity sensor
+    FrontProximity,
+    /// `SW_ROTATE_LOCK`: a screen rotation lock
+    RotateLock,
+    /// `SW_LINEIN_INSERT`: a line in jack
+    LineinInsert,
+    /// `SW_MUTE_DEVICE`: a mute switch
+    MuteDevice,
+    /// `SW_PEN_INSERTED`: a pen garage
+    PenInserted,
+    /// `SW_MACHINE_COVER`: a machine cover, e.g. a printer's lid
+    MachineCover,
+}
+
+impl SwitchType {
+    /// Returns the switch for the kernel's `SW_*` value, if any
+    pub(crate) fn from_raw(code: u16) -> Option<Self> {
+        let s = match code {
+            0x00 => SwitchType::Lid,
+            0x01 => SwitchType::TabletMode,
+            0x02 => SwitchType::HeadphoneInsert,
+            0x03 => SwitchType::RfkillAll,
+            0x04 => SwitchType::MicrophoneInsert,
+            0x05 => SwitchType::Dock,
+            0x06 => SwitchType::LineoutInsert,
+            0x07 => SwitchType::JackPhysicalInsert,
+            0x08 => SwitchType::VideooutInsert,
+            0x09 => SwitchType::CameraLensCover,
+            0x0a => SwitchType::KeypadSlide,
+            0x0b => SwitchType::FrontProximity,
+            0x0c => SwitchType::RotateLock,
+            0x0d => SwitchType::LineinInsert,
+            0x0e => SwitchType::MuteDevice,
+            0x0f => SwitchType::PenInserted,
+            0x10 => SwitchType::MachineCover,
+            _ => return None,
+        };
+        Some(s)
+    }
 }
 
 /// Internal helper for converting to/from [`Capability`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
 struct Cap {
     mask: u32,
 }
@@ -164,6 +697,11 @@ impl Cap {
             Capability::TabletExternal => 1 << 12,
             Capability::TabletPad => 1 << 13,
             Capability::Switch => 1 << 14,
+            Capability::RacingWheel => 1 << 15,
+            Capability::Stylus => 1 << 16,
+            Capability::Dial => 1 << 17,
+            Capability::Accelerometer => 1 << 18,
+            Capability::Numpad => 1 << 19,
         }
     }
 
@@ -184,6 +722,11 @@ impl Cap {
             0b0001000000000000 => Capability::TabletExternal,
             0b0010000000000000 => Capability::TabletPad,
             0b0100000000000000 => Capability::Switch,
+            0b1000000000000000 => Capability::RacingWheel,
+            0b10000000000000000 => Capability::Stylus,
+            0b100000000000000000 => Capability::Dial,
+            0b1000000000000000000 => Capability::Accelerometer,
+            0b10000000000000000000 => Capability::Numpad,
             _ => return None,
         };
         Some(c)
@@ -193,6 +736,10 @@ impl Cap {
         self.mask |= Cap::as_mask(cap);
     }
 
+    fn unset(&mut self, cap: Capability) {
+        self.mask &= !Cap::as_mask(cap);
+    }
+
     fn has(&self, cap: Capability) -> bool {
         (self.mask & Cap::as_mask(cap)) != 0
     }
@@ -209,3 +756,198 @@ impl Cap {
         caps
     }
 }
+
+/// A compact set of [`Capability`]s, stored as bitmask of
+/// [`Capability::as_bit`]. The bitmask is stable across versions, so a
+/// [`CapabilitySet::mask`] may be stored e.g. in a fixture file to detect
+/// when a device's classification changes.
+///
+/// ```
+/// use whodat::{Capability, CapabilitySet};
+///
+/// let before = CapabilitySet::from(vec![Capability::Pointer]);
+/// let after = CapabilitySet::from(vec![Capability::Pointer, Capability::Trackball]);
+/// let (added, removed) = before.diff(&after);
+/// assert_eq!(added, vec![Capability::Trackball]);
+/// assert!(removed.is_empty());
+/// assert_eq!(CapabilitySet::from_mask(after.mask()), after);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct CapabilitySet(Cap);
+
+impl CapabilitySet {
+    /// Create a set from a bitmask as returned by [`CapabilitySet::mask`].
+    /// Bits that do not belong to a capability known to this version of
+    /// whodat are ignored.
+    pub fn from_mask(mask: u32) -> Self {
+        let known = Capability::all().iter().fold(0, |m, c| m | c.as_bit());
+        CapabilitySet(Cap { mask: mask & known })
+    }
+
+    /// The bitmask of this set, see [`Capability::as_bit`]
+    pub fn mask(&self) -> u32 {
+        self.0.mask
+    }
+
+    /// Returns true if the set contains the given capability
+    pub fn contains(&self, cap: Capability) -> bool {
+        self.0.has(cap)
+    }
+
+    /// Returns the capabilities in this set, sorted
+    pub fn capabilities(&self) -> Vec<Capability> {
+        let mut caps = self.0.to_vec();
+        caps.sort();
+        caps
+    }
+
+    /// Compare this set to the other set, e.g. the capabilities of a device
+    /// before and after a change. Returns the capabilities only in the other
+    /// set (added) and the capabilities only in this set (removed), both
+    /// sorted.
+    pub fn diff(&self, other: &CapabilitySet) -> (Vec<Capability>, Vec<Capability>) {
+        let added = CapabilitySet(Cap {
+            mask: other.mask() & !self.mask(),
+        });
+        let removed = CapabilitySet(Cap {
+            mask: self.mask() & !other.mask(),
+        });
+        (added.capabilities(), removed.capabilities())
+    }
+}
+
+impl From<Vec<Capability>> for CapabilitySet {
+    fn from(capabilities: Vec<Capability>) -> Self {
+        CapabilitySet(Cap::new(capabilities))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cap_mask_roundtrip() {
+        for c in Capability::all() {
+            assert_eq!(Cap::from_mask(Cap::as_mask(*c)), Some(*c), "{c:?}");
+        }
+        assert_eq!(Cap::from_mask(0), None);
+        assert_eq!(Cap::from_mask(0b11), None);
+    }
+
+    #[test]
+    fn test_capability_string_roundtrip() {
+        for c in Capability::all() {
+            let s = c.to_string();
+            assert_eq!(s.parse::<Capability>(), Ok(*c), "{s}");
+        }
+        assert_eq!(Capability::TabletPad.to_string(), "tablet-pad");
+        assert_eq!(
+            "racing-wheel".parse::<Capability>(),
+            Ok(Capability::RacingWheel)
+        );
+
+        let err = "mouse".parse::<Capability>().unwrap_err();
+        assert!(err.contains("'mouse'"));
+        assert!(err.contains("touchpad"));
+        // Names are case-sensitive
+        assert!("Touchpad".parse::<Capability>().is_err());
+    }
+
+    fn sorted(mut caps: Vec<Capability>) -> Vec<Capability> {
+        caps.sort();
+        caps
+    }
+
+    #[test]
+    fn test_conflicts_with() {
+        use Capability::*;
+
+        for (a, b) in [
+            (TabletScreen, TabletExternal),
+            (Touchscreen, TabletExternal),
+        ] {
+            assert!(a.conflicts_with(b), "{a:?} {b:?}");
+            assert!(b.conflicts_with(a), "{b:?} {a:?}");
+        }
+        assert!(!TabletScreen.conflicts_with(Touchscreen));
+        assert!(!Touchpad.conflicts_with(Clickpad));
+        for c in Capability::all() {
+            assert!(!c.conflicts_with(*c), "{c:?}");
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        use Capability::*;
+
+        let validate = |caps: Vec<Capability>| sorted(Capability::validate(caps));
+        // Neither is more specific, both are dropped
+        assert_eq!(validate(vec![TabletScreen, TabletExternal]), vec![Tablet]);
+        // The external tablet is more specific than the touchscreen
+        assert_eq!(
+            validate(vec![Touchscreen, Tablet, TabletExternal]),
+            vec![Tablet, TabletExternal]
+        );
+        // No conflict, nothing changes
+        assert_eq!(
+            validate(vec![Touchscreen, Tablet, TabletScreen]),
+            vec![Touchscreen, Tablet, TabletScreen]
+        );
+    }
+
+    #[test]
+    fn test_extend() {
+        use Capability::*;
+
+        let extend = |caps: Vec<Capability>| sorted(Capability::extend(caps));
+        assert_eq!(
+            extend(vec![Pressurepad]),
+            vec![Pointer, Touchpad, Clickpad, Pressurepad]
+        );
+        assert_eq!(extend(vec![Clickpad]), vec![Pointer, Touchpad, Clickpad]);
+        assert_eq!(extend(vec![Touchpad]), vec![Pointer, Touchpad]);
+        assert_eq!(extend(vec![Pointingstick]), vec![Pointer, Pointingstick]);
+        assert_eq!(extend(vec![Trackball]), vec![Pointer, Trackball]);
+        assert_eq!(extend(vec![TabletScreen]), vec![Tablet, TabletScreen]);
+        assert_eq!(extend(vec![TabletExternal]), vec![Tablet, TabletExternal]);
+        assert_eq!(extend(vec![Numpad]), vec![Keyboard, Numpad]);
+    }
+
+    #[test]
+    fn test_extend_implies_nothing() {
+        use Capability::*;
+
+        for cap in [Joystick, Gamepad, RacingWheel, Stylus, Dial, Switch] {
+            assert_eq!(sorted(Capability::extend(vec![cap])), vec![cap], "{cap:?}");
+        }
+        assert_eq!(
+            sorted(Capability::extend(vec![Gamepad, Joystick])),
+            vec![Joystick, Gamepad]
+        );
+    }
+
+    #[test]
+    fn test_all_capabilities() {
+        let all = Capability::all();
+        // In declaration order, without duplicates
+        assert!(all.windows(2).all(|w| w[0] < w[1]));
+
+        // Every bit known to Cap is in all() and vice versa
+        let known: Vec<Capability> = (0..32).filter_map(|bit| Cap::from_mask(1 << bit)).collect();
+        assert_eq!(known.len(), all.len());
+        assert!(known.iter().all(|c| all.contains(c)));
+        let mask = all.iter().fold(0, |mask, c| mask | c.as_bit());
+        assert_eq!(mask.count_ones() as usize, all.len());
+    }
+
+    #[test]
+    fn test_capability_order() {
+        use Capability::*;
+
+        let caps = vec![Numpad, Tablet, Dial, Keyboard, RacingWheel, Pointer];
+        let expected = vec![Keyboard, Pointer, RacingWheel, Tablet, Dial, Numpad];
+        assert_eq!(sorted(caps.clone()), expected);
+        assert_eq!(CapabilitySet::from(caps).capabilities(), expected);
+    }
+}
@@ -1,24 +1,148 @@
+#[cfg(feature = "udev")]
+use std::collections::HashMap;
+#[cfg(feature = "udev")]
 use udev;
 
+/// Returns true if the sysfs path is of a virtual device, i.e. one that does
+/// not have a physical device backing it, e.g. a `uinput` device
+pub fn is_virtual(syspath: &std::path::Path) -> bool {
+    syspath.starts_with("/sys/devices/virtual")
+}
+
 /// Returns a vector of all `ID_INPUT` properties on this device
+#[cfg(feature = "udev")]
 pub fn input_id_udev_props(d: &udev::Device) -> Vec<String> {
-    let excluded = vec!["ID_INPUT_HEIGHT_MM", "ID_INPUT_WIDTH"];
-    let mut caps = Vec::new();
-
-    for property in d.properties() {
-        if let Some(name) = property.name().to_str() {
-            let namestr = String::from(name);
-            if namestr.starts_with("ID_INPUT") {
-                if excluded.iter().any(|e| name == *e) {
-                    continue;
-                }
-                if let Some(v) = property.value().to_str() {
-                    if v != "0" {
-                        caps.push(String::from(name));
-                    }
-                }
+    input_id_props(
+        d.properties()
+            .filter_map(|p| Some((p.name().to_str()?, p.value().to_str()?))),
+    )
+}
+
+/// Returns the names of all `ID_INPUT` properties in the given name/value
+/// pairs that are not set to `0`, excluding the non-boolean ones
+pub fn input_id_props<'a>(props: impl Iterator<Item = (&'a str, &'a str)>) -> Vec<String> {
+    let excluded = ["ID_INPUT_HEIGHT_MM", "ID_INPUT_WIDTH_MM"];
+    props
+        .filter(|(name, _)| name.starts_with("ID_INPUT") && !excluded.contains(name))
+        .filter(|(_, value)| *value != "0")
+        .map(|(name, _)| name.to_string())
+        .collect()
+}
+
+/// Returns all udev properties on this device. Names and values that are not
+/// valid UTF-8 are converted lossily.
+#[cfg(feature = "udev")]
+pub fn all_udev_props(d: &udev::Device) -> HashMap<String, String> {
+    d.properties()
+        .map(|p| {
+            (
+                p.name().to_string_lossy().into_owned(),
+                p.value().to_string_lossy().into_owned(),
+            )
+        })
+        .collect()
+}
+
+/// Returns the name of the kernel driver bound to the closest ancestor of
+/// this device that has a driver, e.g. `hid-generic` or `wacom`. An input
+/// device itself never has a driver.
+#[cfg(feature = "udev")]
+pub fn udev_driver(d: &udev::Device) -> Option<String> {
+    let mut device = d.parent();
+    while let Some(d) = device {
+        if let Some(driver) = d.driver().filter(|drv| !drv.is_empty()) {
+            return Some(driver.to_string_lossy().into_owned());
+        }
+        device = d.parent();
+    }
+    None
+}
+
+/// Returns the udev device in the given subsystem with the given character
+/// device number, e.g. the `st_rdev` of an opened device node.
+///
+/// The device is looked up directly by its number, see
+/// [`udev_device_from_devnum`]. Only where that fails, the subsystem is
+/// enumerated, see [`udev_devnums`].
+///
+/// Returns [`Error::DeviceGone`](crate::Error::DeviceGone) if there is no
+/// such device, e.g. because it was removed since its node was opened.
+#[cfg(feature = "udev")]
+pub fn udev_device_by_devnum(
+    subsystem: &str,
+    sysname: &str,
+    devnum: u64,
+) -> Result<udev::Device, crate::Error> {
+    if let Some(device) = udev_device_from_devnum(subsystem, devnum) {
+        return Ok(device);
+    }
+
+    udev_devnums(subsystem, sysname)?
+        .into_iter()
+        .find(|(n, _)| *n == devnum)
+        .and_then(|(_, syspath)| udev::Device::from_syspath(&syspath).ok())
+        .ok_or(crate::Error::DeviceGone)
+}
+
+/// Returns the udev device in the given subsystem with the given character
+/// device number, looked up directly via `/sys/dev/char`. Returns `None` if
+/// there is no such device or it is in a different subsystem.
+#[cfg(feature = "udev")]
+pub fn udev_device_from_devnum(subsystem: &str, devnum: u64) -> Option<udev::Device> {
+    udev::Device::from_devnum(udev::DeviceType::Character, devnum)
+        .ok()
+        .filter(|d| d.subsystem().map_or(false, |s| s == subsystem))
+}
+
+/// Enumerate the given subsystem and return the device number and sysfs
+/// path of each device, considering only devices whose sysname matches the
+/// glob, e.g. `event*`, so we do not look at every `inputN` or `mouseN`
+/// device.
+#[cfg(feature = "udev")]
+pub fn udev_devnums(
+    subsystem: &str,
+    sysname: &str,
+) -> Result<Vec<(u64, std::path::PathBuf)>, crate::Error> {
+    let mut e = udev::Enumerator::new().map_err(crate::Error::Udev)?;
+    e.match_subsystem(subsystem).map_err(crate::Error::Udev)?;
+    e.match_sysname(sysname).map_err(crate::Error::Udev)?;
+    Ok(e.scan_devices()
+        .map_err(crate::Error::Udev)?
+        .filter_map(|d| d.devnum().map(|n| (n, d.syspath().to_path_buf())))
+        .collect())
+}
+
+/// Returns the value of the given udev property as integer, if the
+/// property is set and numeric
+#[cfg(feature = "udev")]
+pub fn udev_prop_u32(d: &udev::Device, name: &str) -> Option<u32> {
+    d.property_value(name)?.to_str()?.parse().ok()
+}
+
+/// Parse a kernel bitmask as printed in sysfs, e.g. `"1f 0 0 ffff"`, into the
+/// list of bits set. The words are printed most significant first and each word
+/// is a `long`.
+pub fn parse_bitmask(s: &str) -> Vec<u16> {
+    let mut bits = Vec::new();
+    for (i, word) in s.split_whitespace().rev().enumerate() {
+        let word = match u64::from_str_radix(word, 16) {
+            Ok(word) => word,
+            Err(_) => continue,
+        };
+        for bit in 0..usize::BITS {
+            if word & (1 << bit) != 0 {
+                bits.push((i as u32 * usize::BITS + bit) as u16);
             }
         }
     }
-    caps
+    bits
+}
+
+/// Equivalent to the kernel's `_IOC(_IOC_READ, ty, nr, size)` macro
+pub(crate) const fn ioc_read(ty: u8, nr: u8, size: usize) -> libc::c_ulong {
+    const IOC_READ: libc::c_ulong = 2;
+    (IOC_READ << 30)
+        | ((size as libc::c_ulong) << 16)
+        | ((ty as libc::c_ulong) << 8)
+        | (nr as libc::c_ulong)
 }
@@ -1,32 +1,37 @@
+use async_io::Async;
 use std::collections::HashMap;
-use std::os::unix::io::RawFd;
-use std::sync::Arc;
-use zbus::zvariant::ObjectPath;
-use zbus::{dbus_interface, ConnectionBuilder, ObjectServer, Result};
+use std::fs::File;
+use std::io::ErrorKind;
+use std::os::fd::{AsRawFd, BorrowedFd, OwnedFd};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use whodat::{AttachedDevice, DeviceIndex, DeviceTree, HasCapability, HasParent, MonitorEvent};
+use zbus::zvariant::{Fd, ObjectPath};
+use zbus::{dbus_interface, fdo, ConnectionBuilder, ObjectServer, Result, SignalContext};
 
 const PATH_BASE: &'static str = "/org/freedesktop/whodat";
 const VERSION: u32 = 1;
 
-// FIXME: this will eventually be a whodat.Device
-struct InnerDevice {
-    name: String,
-}
-
 struct Device {
-    inner: Arc<InnerDevice>,
+    tree: Arc<Mutex<DeviceTree>>,
+    index: DeviceIndex,
     objpath: String,
     parent_objpath: String,
 }
 
-// FIXME: this will eventually be a whodat.Device or something
 struct PhysicalDevice {
-    inner: Arc<InnerDevice>,
+    tree: Arc<Mutex<DeviceTree>>,
+    /// The object paths of all devices, shared with the [`Daemon`]
+    devices: Arc<Mutex<HashMap<DeviceIndex, String>>>,
+    index: DeviceIndex,
     objpath: String,
 }
 
 struct Daemon {
     counter: u32,
-    devices: HashMap<String, Arc<InnerDevice>>,
+    tree: Arc<Mutex<DeviceTree>>,
+    /// The object paths of all devices registered on the object server
+    devices: Arc<Mutex<HashMap<DeviceIndex, String>>>,
 }
 
 #[dbus_interface(name = "org.freedesktop.Whodat.Device")]
@@ -37,8 +42,24 @@ impl Device {
     }
 
     #[dbus_interface(property)]
-    async fn name(&self) -> &String {
-        &self.inner.name
+    async fn name(&self) -> String {
+        let tree = self.tree.lock().unwrap();
+        match tree.get_device(&self.index) {
+            Some(AttachedDevice::Evdev(d)) => d.name().to_string(),
+            Some(AttachedDevice::Hidraw(d)) => d.name().to_string(),
+            _ => String::new(),
+        }
+    }
+
+    #[dbus_interface(property)]
+    async fn capabilities(&self) -> Vec<String> {
+        let tree = self.tree.lock().unwrap();
+        let caps = match tree.get_device(&self.index) {
+            Some(AttachedDevice::Evdev(d)) => d.capabilities(),
+            Some(AttachedDevice::Hidraw(d)) => d.capabilities(),
+            _ => Vec::new(),
+        };
+        caps.iter().map(|c| format!("{c:?}")).collect()
     }
 
     #[dbus_interface(property)]
@@ -47,12 +68,55 @@ impl Device {
     }
 }
 
-#[dbus_interface(name = "org.freedesktop.Whodat.Device")]
+#[dbus_interface(name = "org.freedesktop.Whodat.PhysicalDevice")]
 impl PhysicalDevice {
     #[dbus_interface(property)]
     async fn version(&self) -> u32 {
         VERSION
     }
+
+    #[dbus_interface(property)]
+    async fn name(&self) -> String {
+        let tree = self.tree.lock().unwrap();
+        tree.get_parent_device(&self.index)
+            .map(|d| d.name().to_string())
+            .unwrap_or_default()
+    }
+
+    #[dbus_interface(property)]
+    async fn capabilities(&self) -> Vec<String> {
+        let tree = self.tree.lock().unwrap();
+        tree.get_parent_device(&self.index)
+            .map(|d| d.capabilities().iter().map(|c| format!("{c:?}")).collect())
+            .unwrap_or_default()
+    }
+
+    /// The object paths of all kernel devices of this physical device
+    #[dbus_interface(property)]
+    async fn children(&self) -> Vec<ObjectPath> {
+        let children: Vec<DeviceIndex> = {
+            let tree = self.tree.lock().unwrap();
+            match tree.get_parent_device(&self.index) {
+                Some(parent) => parent.iter().copied().collect(),
+                None => Vec::new(),
+            }
+        };
+        let devices = self.devices.lock().unwrap();
+        children
+            .iter()
+            .filter_map(|idx| devices.get(idx))
+            .filter_map(|path| ObjectPath::try_from(path.clone()).ok())
+            .collect()
+    }
+
+    /// The abstract type of this device or the empty string if unknown
+    #[dbus_interface(property)]
+    async fn abstract_type(&self) -> String {
+        let tree = self.tree.lock().unwrap();
+        tree.get_parent_device(&self.index)
+            .and_then(|d| d.abstract_types().first().map(|t| format!("{t:?}")))
+            .unwrap_or_default()
+    }
 }
 
 #[dbus_interface(name = "org.freedesktop.Whodat")]
@@ -70,26 +134,136 @@ impl Daemon {
     async fn device_from_evdev(
         &mut self,
         #[zbus(object_server)] object_server: &ObjectServer,
-        fd: RawFd,
-    ) -> ObjectPath {
-        self.counter += 1;
-        let path = format!("{PATH_BASE}/e/{}", self.counter);
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        fd: Fd,
+    ) -> fdo::Result<ObjectPath> {
+        let fd = unsafe { BorrowedFd::borrow_raw(fd.as_raw_fd()) }
+            .try_clone_to_owned()
+            .map_err(|e| fdo::Error::IOError(e.to_string()))?;
+
+        let index = self
+            .tree
+            .lock()
+            .unwrap()
+            .attach_evdev(fd)
+            .map_err(|e| fdo::Error::Failed(e.to_string()))?;
+
+        Ok(self.register(object_server, Some(&ctxt), index).await)
+    }
+
+    /// Creates a new whodat.Device given the path to an evdev or hidraw
+    /// device node and returns the object path for that device.
+    ///
+    /// The node is opened by the daemon so the caller does not need to
+    /// have access to the node. Only nodes in `/dev/input` and `/dev/hidraw*`
+    /// are accepted.
+    async fn device_from_path(
+        &mut self,
+        #[zbus(object_server)] object_server: &ObjectServer,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        path: String,
+    ) -> fdo::Result<ObjectPath> {
+        // Resolve symlinks like /dev/input/by-id before checking the prefix
+        let path = std::fs::canonicalize(&path)
+            .map_err(|e| fdo::Error::InvalidArgs(format!("{path}: {e}")))?;
+        let is_evdev = path.starts_with("/dev/input")
+            && path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with("event"));
+        let is_hidraw = path.parent() == Some(Path::new("/dev"))
+            && path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with("hidraw"));
+        if !is_evdev && !is_hidraw {
+            return Err(fdo::Error::InvalidArgs(format!(
+                "{}: not an evdev or hidraw device node",
+                path.display()
+            )));
+        }
 
-        let inner = Arc::new(InnerDevice {
-            name: String::from("evdev device"),
-        });
+        let fd: OwnedFd = File::open(&path)
+            .map_err(|e| match e.kind() {
+                ErrorKind::PermissionDenied => fdo::Error::AccessDenied(e.to_string()),
+                ErrorKind::NotFound => fdo::Error::FileNotFound(e.to_string()),
+                _ => fdo::Error::IOError(e.to_string()),
+            })?
+            .into();
 
-        let parent_path = format!("{PATH_BASE}/p/{}", self.counter);
-        let parent = PhysicalDevice {
-            inner: inner.clone(), // FIXME: needs to be its own device obviously
-            objpath: parent_path.clone(),
+        let index = {
+            let mut tree = self.tree.lock().unwrap();
+            if is_evdev {
+                tree.attach_evdev(fd)
+            } else {
+                tree.attach_hidraw(fd)
+            }
+        }
+        .map_err(|e| fdo::Error::Failed(e.to_string()))?;
+
+        Ok(self.register(object_server, Some(&ctxt), index).await)
+    }
+
+    /// Emitted after a device object was registered on the object server
+    #[dbus_interface(signal)]
+    async fn device_added(ctxt: &SignalContext<'_>, device: ObjectPath<'_>) -> Result<()>;
+
+    /// Emitted after a device object was removed from the object server
+    #[dbus_interface(signal)]
+    async fn device_removed(ctxt: &SignalContext<'_>, device: ObjectPath<'_>) -> Result<()>;
+}
+
+impl Daemon {
+    /// Register the object for the kernel device with the given index and,
+    /// if it is not yet registered, the object for its parent. Kernel devices
+    /// are at `/e/N` (evdev) or `/h/N` (hidraw), parents at `/p/N`.
+    ///
+    /// If a signal context is given, `DeviceAdded` is emitted for each new
+    /// object, the parent first.
+    async fn register(
+        &mut self,
+        object_server: &ObjectServer,
+        ctxt: Option<&SignalContext<'_>>,
+        index: DeviceIndex,
+    ) -> ObjectPath<'static> {
+        let (pindex, prefix) = {
+            let tree = self.tree.lock().unwrap();
+            match tree.get_device(&index) {
+                Some(AttachedDevice::Hidraw(d)) => (d.parent(), "h"),
+                Some(AttachedDevice::Evdev(d)) => (d.parent(), "e"),
+                _ => panic!("Only kernel devices can be registered"),
+            }
         };
 
-        let parent_objpath = ObjectPath::try_from(parent.objpath.clone()).unwrap();
-        let _ = object_server.at(&parent_objpath, parent).await;
+        self.counter += 1;
+
+        // The parent may already be registered by one of its other children
+        let registered = self.devices.lock().unwrap().get(&pindex).cloned();
+        let parent_path = match registered {
+            Some(path) => path.clone(),
+            None => {
+                let parent_path = format!("{PATH_BASE}/p/{}", self.counter);
+                let parent = PhysicalDevice {
+                    tree: self.tree.clone(),
+                    devices: self.devices.clone(),
+                    index: pindex,
+                    objpath: parent_path.clone(),
+                };
 
+                let parent_objpath = ObjectPath::try_from(parent.objpath.clone()).unwrap();
+                let _ = object_server.at(&parent_objpath, parent).await;
+                self.devices.lock().unwrap().insert(pindex, parent_path.clone());
+                if let Some(ctxt) = ctxt {
+                    let _ = Self::device_added(ctxt, parent_objpath).await;
+                }
+                parent_path
+            }
+        };
+
+        let path = format!("{PATH_BASE}/{prefix}/{}", self.counter);
         let device = Device {
-            inner: inner.clone(),
+            tree: self.tree.clone(),
+            index,
             objpath: path.clone(),
             parent_objpath: parent_path,
         };
@@ -97,26 +271,102 @@ impl Daemon {
         let objpath = ObjectPath::try_from(device.objpath.clone()).unwrap();
         let _ = object_server.at(&objpath, device).await;
 
-        self.devices.insert(path, inner);
+        self.devices.lock().unwrap().insert(index, path);
+        if let Some(ctxt) = ctxt {
+            let _ = Self::device_added(ctxt, objpath.clone()).await;
+        }
 
         objpath
     }
+
+    /// Remove the object for the device with the given index from the object
+    /// server and emit `DeviceRemoved`. The device must already be removed
+    /// from the tree, its parent is unregistered separately.
+    async fn unregister(
+        &mut self,
+        object_server: &ObjectServer,
+        ctxt: &SignalContext<'_>,
+        index: DeviceIndex,
+    ) {
+        let path = match self.devices.lock().unwrap().remove(&index) {
+            Some(path) => path,
+            None => return,
+        };
+        let objpath = ObjectPath::try_from(path.clone()).unwrap();
+        let removed = if path.starts_with(&format!("{PATH_BASE}/p/")) {
+            object_server.remove::<PhysicalDevice, _>(&objpath).await
+        } else {
+            object_server.remove::<Device, _>(&objpath).await
+        };
+        if let Ok(true) = removed {
+            let _ = Self::device_removed(ctxt, objpath).await;
+        }
+    }
 }
 
 #[async_std::main]
 async fn main() -> Result<()> {
-    let daemon = Daemon {
+    let mut daemon = Daemon {
         counter: 0,
-        devices: HashMap::new(),
+        tree: Arc::new(Mutex::new(DeviceTree::new())),
+        devices: Arc::new(Mutex::new(HashMap::new())),
     };
 
-    let _connection = ConnectionBuilder::session()?
-        .name("org.freedesktop.Whodat")?
-        .serve_at(PATH_BASE, daemon)?
-        .build()
-        .await?;
+    let connection = ConnectionBuilder::session()?.build().await?;
+
+    // Register all devices present at startup before we take the bus name
+    // so a client never sees a partially populated daemon. Nobody can
+    // listen for DeviceAdded yet, so no signals are emitted.
+    let indices = daemon
+        .tree
+        .lock()
+        .unwrap()
+        .attach_system()
+        .map_err(|e| zbus::Error::Failure(e.to_string()))?;
+    for index in indices {
+        daemon
+            .register(&connection.object_server(), None, index)
+            .await;
+    }
 
+    let tree = daemon.tree.clone();
+    let monitor = DeviceTree::monitor().map_err(|e| zbus::Error::Failure(e.to_string()))?;
+    let mut monitor = Async::new(monitor)?;
+
+    connection.object_server().at(PATH_BASE, daemon).await?;
+    connection.request_name("org.freedesktop.Whodat").await?;
+
+    let iface = connection
+        .object_server()
+        .interface::<_, Daemon>(PATH_BASE)
+        .await?;
     loop {
-        std::future::pending::<()>().await;
+        monitor.readable().await?;
+        loop {
+            let event = monitor.get_mut().next_event(&mut tree.lock().unwrap());
+            let event = match event {
+                Ok(Some(event)) => event,
+                Ok(None) => break,
+                Err(e) => {
+                    eprintln!("Failed to process device events: {e}");
+                    break;
+                }
+            };
+            let object_server = connection.object_server();
+            let mut daemon = iface.get_mut().await;
+            match event {
+                MonitorEvent::Added(index) => {
+                    daemon
+                        .register(&object_server, Some(iface.signal_context()), index)
+                        .await;
+                }
+                MonitorEvent::Removed(index) => {
+                    daemon
+                        .unregister(&object_server, iface.signal_context(), index)
+                        .await;
+                }
+                _ => {}
+            }
+        }
     }
 }